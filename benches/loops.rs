@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use maymun_lang::eval::eval_program;
+use maymun_lang::lexer::Lexer;
+use maymun_lang::object::Environment;
+use maymun_lang::parser::Parser;
+
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let elements: Vec<String> = (0..2_000).map(|i| i.to_string()).collect();
+    let array = format!("let a = [{}];", elements.join(", "));
+
+    bench(
+        "counting while loop",
+        "let i = 0; let sum = 0; while (i < 20000) { sum += i; i += 1; } sum",
+    );
+    bench(
+        "indexing a bound array",
+        &format!(
+            "{} let i = 0; let sum = 0; while (i < len(a)) {{ sum += a[i]; i += 1; }} sum",
+            array
+        ),
+    );
+    bench(
+        "for-in over a bound array",
+        &format!("{} let sum = 0; for (x in a) {{ sum += x; }} sum", array),
+    );
+    bench(
+        "map and reduce over a bound array",
+        &format!("{} reduce(map(a, fn(x) {{ x * 2 }}), 0, (+))", array),
+    );
+}
+
+fn bench(name: &str, input: &str) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let env = Environment::new().into();
+
+        eval_program(parser.parse_program(), &env).unwrap();
+    }
+
+    println!("{}: {:?} per run", name, start.elapsed() / ITERATIONS);
+}
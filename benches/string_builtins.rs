@@ -0,0 +1,33 @@
+use std::time::Instant;
+
+use maymun_lang::eval::eval_program;
+use maymun_lang::lexer::Lexer;
+use maymun_lang::object::Environment;
+use maymun_lang::parser::Parser;
+
+const ITERATIONS: u32 = 200;
+
+fn main() {
+    let text = "  Some Mixed CASE text with padding  ".repeat(2_000);
+    let chained = format!("lower(trim(replace(\"{}\", \"a\", \"o\")))", text);
+    let bound = format!(
+        "let s = replace(\"{}\", \"a\", \"o\"); let t = trim(s); lower(t)",
+        text
+    );
+
+    bench("chained string builtins", &chained);
+    bench("string builtins through bindings", &bound);
+}
+
+fn bench(name: &str, input: &str) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let env = Environment::new().into();
+
+        eval_program(parser.parse_program(), &env).unwrap();
+    }
+
+    println!("{}: {:?} per run", name, start.elapsed() / ITERATIONS);
+}
@@ -1,17 +1,46 @@
 use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use crate::lexer::escape;
 
 pub type Identifier = String;
 pub type Operator = String;
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Expression {
     Literal(String),
     Int(i64),
+    Float(f64),
+    String(String),
     Prefix(Operator, Box<Expression>),
     Infix(Box<Expression>, Operator, Box<Expression>),
     Boolean(bool),
+    Null,
     If(Box<Expression>, BlockStatement, Option<BlockStatement>),
-    Function(Vec<Identifier>, BlockStatement),
+    Ternary(Box<Expression>, Box<Expression>, Box<Expression>),
+    // `do { ... }`, a block with its own scope evaluating to its last statement.
+    Do(BlockStatement),
+    // `match (value) { pattern => { ... }, ... }`, running the first arm whose
+    // pattern matches.
+    Match(Box<Expression>, Vec<(Pattern, BlockStatement)>),
+    // Parameters, then the optional `...rest` parameter collecting any extra
+    // arguments into an array.
+    Function(Vec<Identifier>, Option<Identifier>, Rc<BlockStatement>),
+    #[allow(clippy::vec_box)]
     Call(Box<Expression>, Vec<Box<Expression>>),
+    // `receiver.method(args)`, a call of the builtin `method` with the receiver
+    // as its first argument.
+    MethodCall(Box<Expression>, Identifier, Vec<Expression>),
+    Array(Vec<Expression>),
+    Index(Box<Expression>, Box<Expression>),
+    Hash(Vec<(Expression, Expression)>),
+    OperatorFunction(Operator),
+    Assign(Identifier, Box<Expression>),
+    // `name[index] = value`, replacing one element of a bound array or hash.
+    IndexAssign(Identifier, Box<Expression>, Box<Expression>),
+    // `name[index] op= value`, kept whole so `index` is evaluated only once.
+    CompoundIndexAssign(Identifier, Box<Expression>, Operator, Box<Expression>),
 }
 
 impl Display for Expression {
@@ -19,68 +48,205 @@ impl Display for Expression {
         match self {
             Expression::Literal(literal) => write!(f, "{}", literal),
             Expression::Int(int) => write!(f, "{}", int),
+            Expression::Float(float) => write!(f, "{:?}", float),
+            Expression::String(string) => write!(f, "\"{}\"", escape(string)),
             Expression::Boolean(val) => write!(f, "{}", val),
+            Expression::Null => write!(f, "null"),
             Expression::Prefix(operator, right) => {
-                write!(f, "({}{})", operator, right.to_string())
+                write!(f, "({}{})", operator, right)
             }
             Expression::Infix(left, operator, right) => {
-                write!(
-                    f,
-                    "({} {} {})",
-                    left.to_string(),
-                    operator,
-                    right.to_string()
-                )
+                write!(f, "({} {} {})", left, operator, right)
             }
             Expression::If(expression, consequence, alternative) => {
-                let mut s = format!("if {} {{ ", expression);
-                for stmt in consequence {
-                    s.push_str(&stmt.to_string())
-                }
-                s.push_str(" } ");
+                let mut s = format!("if {} {{ ", parenthesized(expression));
+                s.push_str(&statement_list(consequence));
+                s.push_str(" }");
 
                 if let Some(alternative) = alternative {
-                    s.push_str("else { ");
-                    for stmt in alternative {
-                        s.push_str(&stmt.to_string())
-                    }
+                    s.push_str(" else { ");
+                    s.push_str(&statement_list(alternative));
                     s.push_str(" }");
                 }
 
                 write!(f, "{}", s)
             }
-            Expression::Function(parameters, body) => {
-                let mut s = String::new();
-                for stmt in body {
-                    s.push_str(&stmt.to_string());
+            Expression::Ternary(cond, conseq, alter) => {
+                write!(f, "({} ? {} : {})", cond, conseq, alter)
+            }
+            Expression::Do(body) => write!(f, "do {{ {} }}", statement_list(body)),
+            Expression::Match(value, arms) => {
+                let mut s = vec![];
+                for (pattern, body) in arms {
+                    s.push(format!("{} => {{ {} }}", pattern, statement_list(body)));
                 }
 
-                write!(f, "fn({}) {{ {} }}", parameters.join(", "), s)
+                write!(f, "match {} {{ {} }}", parenthesized(value), s.join(", "))
             }
+            Expression::Function(parameters, rest, body) => write!(
+                f,
+                "fn({}) {{ {} }}",
+                parameter_list(parameters, rest),
+                statement_list(body)
+            ),
             Expression::Call(function, arguments) => {
                 let mut s = vec![];
                 for arg in arguments {
                     s.push(arg.to_string());
                 }
 
-                write!(f, "{}({})", function.to_string(), s.join(", "))
+                write!(f, "{}({})", function, s.join(", "))
+            }
+            Expression::MethodCall(receiver, method, arguments) => {
+                let mut s = vec![];
+                for arg in arguments {
+                    s.push(arg.to_string());
+                }
+
+                write!(f, "{}.{}({})", receiver, method, s.join(", "))
+            }
+            Expression::Array(elements) => {
+                let mut s = vec![];
+                for element in elements {
+                    s.push(element.to_string());
+                }
+
+                write!(f, "[{}]", s.join(", "))
+            }
+            Expression::Index(left, index) => write!(f, "({}[{}])", left, index),
+            Expression::Hash(pairs) => {
+                let mut s = vec![];
+                for (key, value) in pairs {
+                    s.push(format!("{}: {}", key, value));
+                }
+
+                write!(f, "{{{}}}", s.join(", "))
+            }
+            Expression::OperatorFunction(operator) => write!(f, "({})", operator),
+            Expression::Assign(name, value) => write!(f, "({} = {})", name, value),
+            Expression::IndexAssign(name, index, value) => {
+                write!(f, "({}[{}] = {})", name, index, value)
             }
+            Expression::CompoundIndexAssign(name, index, op, value) => {
+                write!(f, "({}[{}] {}= {})", name, index, op, value)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Pattern {
+    Int(i64),
+    Boolean(bool),
+    String(String),
+    Wildcard,
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Int(int) => write!(f, "{}", int),
+            Pattern::Boolean(val) => write!(f, "{}", val),
+            Pattern::String(string) => write!(f, "\"{}\"", escape(string)),
+            Pattern::Wildcard => write!(f, "_"),
         }
     }
 }
 
+// Writes statements back to back, ending an expression statement with `;` when
+// another statement follows so the two can't run together, as in `x(-1)`.
+pub(crate) fn statement_list(stmts: &[Statement]) -> String {
+    let mut s = String::new();
+    for (i, stmt) in stmts.iter().enumerate() {
+        s.push_str(&stmt.to_string());
+        if matches!(stmt, Statement::Expression(_)) && i + 1 < stmts.len() {
+            s.push(';');
+        }
+    }
+
+    s
+}
+
+// Conditions are written in parentheses, which infix and prefix expressions
+// already carry.
+fn parenthesized(expr: &Expression) -> String {
+    match expr {
+        Expression::Prefix(..)
+        | Expression::Infix(..)
+        | Expression::Ternary(..)
+        | Expression::Index(..)
+        | Expression::Assign(..)
+        | Expression::IndexAssign(..)
+        | Expression::CompoundIndexAssign(..) => expr.to_string(),
+        _ => format!("({})", expr),
+    }
+}
+
+pub(crate) fn parameter_list(parameters: &[Identifier], rest: &Option<Identifier>) -> String {
+    let mut list = parameters.to_vec();
+    if let Some(rest) = rest {
+        list.push(format!("...{}", rest));
+    }
+
+    list.join(", ")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Statement {
     Let(Identifier, Expression),
     Return(Expression),
     Expression(Expression),
+    While(Expression, BlockStatement),
+    ForIn(Identifier, Expression, BlockStatement),
+    // `for (init; condition; post) { ... }`, where `init` is a `let` or an
+    // expression statement and `post` an expression statement.
+    ForC(Box<Statement>, Expression, Box<Statement>, BlockStatement),
+    // A bare `{ ... }` at statement start, with its own scope. A `{` there that
+    // is closed right away or whose first expression is followed by a `:` opens
+    // a hash literal instead.
+    Block(BlockStatement),
+    Break,
+    Continue,
 }
 
 impl Display for Statement {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Statement::Let(i, e) => write!(f, "let {} = {};", i, e.to_string()),
+            Statement::Let(i, e) => write!(f, "let {} = {};", i, e),
             Statement::Return(e) => write!(f, "return {};", e),
             Statement::Expression(e) => write!(f, "{}", e),
+            Statement::While(condition, body) => write!(
+                f,
+                "while {} {{ {} }}",
+                parenthesized(condition),
+                statement_list(body)
+            ),
+            Statement::ForIn(ident, iterable, body) => write!(
+                f,
+                "for ({} in {}) {{ {} }}",
+                ident,
+                iterable,
+                statement_list(body)
+            ),
+            Statement::ForC(init, condition, post, body) => {
+                let init = match **init {
+                    Statement::Let(..) => init.to_string(),
+                    _ => format!("{};", init),
+                };
+                write!(
+                    f,
+                    "for ({} {}; {}) {{ {} }}",
+                    init,
+                    condition,
+                    post,
+                    statement_list(body)
+                )
+            }
+            Statement::Block(body) => write!(f, "{{ {} }}", statement_list(body)),
+            Statement::Break => write!(f, "break;"),
+            Statement::Continue => write!(f, "continue;"),
         }
     }
 }
@@ -89,52 +255,213 @@ pub type BlockStatement = Vec<Statement>;
 
 pub type Statements = Vec<Statement>;
 
-pub struct Program(Statements);
+// Each top-level statement is stored with the source line it starts on, so
+// runtime errors can point back at it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Program {
+    statements: Statements,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lines: Vec<usize>,
+}
 
+#[allow(clippy::new_without_default, clippy::len_without_is_empty)]
 impl Program {
     pub fn new() -> Self {
-        Self(Statements::new())
+        Self {
+            statements: Statements::new(),
+            lines: vec![],
+        }
     }
 
     pub fn all(&self) -> &Statements {
-        &self.0
+        &self.statements
     }
 
     pub fn get(&self, i: usize) -> &Statement {
-        self.0.get(i).unwrap()
+        self.statements.get(i).unwrap()
     }
 
-    pub fn push(&mut self, s: Statement) {
-        self.0.push(s);
+    pub fn line(&self, i: usize) -> usize {
+        self.lines[i]
+    }
+
+    pub fn push(&mut self, s: Statement, line: usize) {
+        self.statements.push(s);
+        self.lines.push(line);
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.statements.len()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
     }
 }
 
+// The output is valid source that parses back to the same statements, though
+// not necessarily as the original was written: every infix and prefix
+// expression gets its own parentheses, for one.
 impl Display for Program {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut o = String::new();
-        for s in self.0.iter() {
-            o.push_str(&s.to_string());
-        }
-
-        write!(f, "{}", o)
+        write!(f, "{}", statement_list(&self.statements))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
 
     #[test]
     fn test_string() {
-        let program = Program(Statements::from([Statement::Let(
-            Identifier::from("myVar"),
-            Expression::Literal("anotherVar".to_string()),
-        )]));
+        let mut program = Program::new();
+        program.push(
+            Statement::Let(
+                Identifier::from("myVar"),
+                Expression::Literal("anotherVar".to_string()),
+            ),
+            1,
+        );
 
         assert_eq!(program.to_string(), "let myVar = anotherVar;");
     }
+
+    #[test]
+    fn test_equality_and_clone() {
+        let build = || {
+            Expression::Infix(
+                Box::new(Expression::Literal("x".to_string())),
+                "+".to_string(),
+                Box::new(Expression::Call(
+                    Box::new(Expression::Literal("f".to_string())),
+                    vec![
+                        Box::new(Expression::Int(1)),
+                        Box::new(Expression::Float(2.5)),
+                    ],
+                )),
+            )
+        };
+
+        assert_eq!(build(), build());
+        assert_ne!(build(), Expression::Int(1));
+
+        let mut program = Program::new();
+        program.push(Statement::Let("y".to_string(), build()), 1);
+        program.push(
+            Statement::While(
+                Expression::Boolean(true),
+                vec![Statement::Return(Expression::Function(
+                    vec!["a".to_string()],
+                    None,
+                    Rc::new(vec![Statement::Expression(build())]),
+                ))],
+            ),
+            2,
+        );
+        let cloned = program.clone();
+
+        assert_eq!(program, cloned);
+        assert_eq!(program.to_string(), cloned.to_string());
+    }
+
+    #[test]
+    fn test_string_display_escapes() {
+        let expr = Expression::String("a\"b\\c\nd\te".to_string());
+
+        assert_eq!(r#""a\"b\\c\nd\te""#, expr.to_string());
+    }
+
+    #[test]
+    fn test_return_round_trip() {
+        let parse = |input: &str| Parser::new(Lexer::new(input)).parse_program();
+
+        let program = parse("return 5;");
+        assert_eq!("return 5;", program.to_string());
+        assert_eq!(program, parse(&program.to_string()));
+
+        let program = parse("return 1; let x = 2;");
+        assert_eq!("return 1;let x = 2;", program.to_string());
+        assert_eq!(program, parse(&program.to_string()));
+    }
+
+    #[test]
+    fn test_if_display_has_braces() {
+        let mut parser = Parser::new(Lexer::new("if (x) { y }"));
+        let program = parser.parse_program();
+        let displayed = program.to_string();
+
+        assert_eq!("if (x) { y }", displayed);
+        assert_eq!(
+            program.all(),
+            Parser::new(Lexer::new(&displayed)).parse_program().all()
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        let inputs = [
+            "let x = 5 * (2 + -y) / z;",
+            "x; -1",
+            "f(x); (a + b) * 2",
+            "a[0]; [1, 2][1]",
+            "let add = fn(x, y) { x + y; }; add(1, 2)",
+            "fn(a, ...rest) { rest }(1, 2, 3)",
+            "if (x < y) { x; -y } else { y }",
+            "if (x) { y }; if (f(x)) { 1 } else { if (!x) { 2 } }",
+            "!true == false; 1 < 2 != 3 >= 4",
+            "1 | 2 ^ 3 & 4 << 5 >> 6; ~x",
+            "let s = \"a\\\"b\\n\" + str(1.5);",
+            "{\"a\": [1, null], true: {}, 2: (+)}",
+            "{ let x = 1; { x; {} } } ({}[1]); {}",
+            "x > 0 ? 1 : x < 0 ? -1 : 0",
+            "[1, 2].len() + \"s\".upper().len()",
+            "x = y = 2; a[i + 1] += -a[i]; (x = 3) + 1",
+            "while (x) { x -= 1; break; continue; }",
+            "while (i < n) { i += 1 }",
+            "for (x in xs) { puts(x); }",
+            "for (let i = 0; i < 3; i += 1) { i; }",
+            "for (i = 0; i; i = i - 1) {}",
+            "let v = do { let a = 1; a; a + 1 };",
+            "match (x) { 1 => { \"one\" }, -1 => {}, \"s\" => { x; -x }, _ => { null } }",
+            "match (x + 1) { true => { 1 } }",
+            "fn named(x) { return x; }",
+        ];
+
+        for input in inputs {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+            assert!(
+                parser.errors().is_empty(),
+                "{}: {:?}",
+                input,
+                parser.errors()
+            );
+
+            let displayed = program.to_string();
+            let mut parser = Parser::new(Lexer::new(&displayed));
+            let reparsed = parser.parse_program();
+            assert!(
+                parser.errors().is_empty(),
+                "{}: {:?}",
+                displayed,
+                parser.errors()
+            );
+            assert_eq!(program.all(), reparsed.all(), "{}", displayed);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_json() {
+        let program = Parser::new(Lexer::new("let x = 1 + 2;")).parse_program();
+
+        assert_eq!(
+            r#"{"statements":[{"Let":["x",{"Infix":[{"Int":1},"+",{"Int":2}]}]}]}"#,
+            program.to_json().unwrap()
+        );
+    }
 }
@@ -0,0 +1,501 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::eval::{eval_program_with, EvalContext};
+use crate::fmt::{format_program_with, FormatOptions, Indent};
+use crate::lexer::Lexer;
+use crate::object::{Environment, Object};
+use crate::parser::{ParseError, Parser};
+use crate::repl;
+
+const USAGE: &str = "usage: maymun [<file> | - | -e <source> | fmt [--indent <n|tab>] <file> | --check [--format=<text|json>] <file> | --watch <file>]";
+
+const WATCH_SEPARATOR: &str = "----";
+
+pub fn run<R, W, E>(args: &[String], mut input: R, mut output: W, mut error: E) -> i32
+where
+    R: Read,
+    W: Write,
+    E: Write,
+{
+    match args.first().map(String::as_str) {
+        None => {
+            writeln!(output, "Hello! This is the Maymun programming language!").unwrap();
+            writeln!(output, "Feel free to type in commands").unwrap();
+
+            repl::start(input, output);
+            0
+        }
+        // Reads the whole program from `input`, which is how piped source is run.
+        Some("-") if args.len() == 1 => {
+            let mut source = String::new();
+            match input.read_to_string(&mut source) {
+                Ok(_) => eval_source(&source, output, error),
+                Err(err) => {
+                    writeln!(error, "cannot read stdin: {}", err).unwrap();
+                    1
+                }
+            }
+        }
+        Some("-e") => match &args[1..] {
+            [source] => eval_source(source, output, error),
+            _ => {
+                writeln!(error, "{}", USAGE).unwrap();
+                2
+            }
+        },
+        Some("fmt") => run_fmt(&args[1..], output, error),
+        Some("--check") => run_check(&args[1..], output, error),
+        Some("--watch") => match &args[1..] {
+            [path] => watch(Path::new(path), &mut MtimePoller::default(), output, error),
+            _ => {
+                writeln!(error, "{}", USAGE).unwrap();
+                2
+            }
+        },
+        Some(path) if args.len() == 1 && !path.starts_with('-') => run_script(path, output, error),
+        Some(_) => {
+            writeln!(error, "{}", USAGE).unwrap();
+            2
+        }
+    }
+}
+
+fn run_fmt<W, E>(args: &[String], mut output: W, mut error: E) -> i32
+where
+    W: Write,
+    E: Write,
+{
+    let mut options = FormatOptions::default();
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--indent" => match args.next().map(String::as_str) {
+                Some("tab") => options.indent = Indent::Tab,
+                Some(n) if n.parse::<usize>().is_ok() => {
+                    options.indent = Indent::Spaces(n.parse().unwrap())
+                }
+                _ => {
+                    writeln!(error, "--indent expects a number of spaces or `tab`").unwrap();
+                    return 2;
+                }
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                writeln!(error, "{}", USAGE).unwrap();
+                return 2;
+            }
+        }
+    }
+
+    let Some(path) = path else {
+        writeln!(error, "{}", USAGE).unwrap();
+        return 2;
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            writeln!(error, "cannot read {}: {}", path, err).unwrap();
+            return 1;
+        }
+    };
+
+    match format_program_with(&source, &options) {
+        Ok(formatted) => {
+            write!(output, "{}", formatted).unwrap();
+            0
+        }
+        Err(errors) => {
+            for err in errors {
+                writeln!(error, "\t{}", err).unwrap();
+            }
+            1
+        }
+    }
+}
+
+// Blocks until the watched file should be evaluated again. Returning false ends
+// the watch, which lets tests drive a fixed number of runs.
+pub trait Poller {
+    fn wait_for_change(&mut self, path: &Path) -> bool;
+}
+
+pub struct MtimePoller {
+    interval: Duration,
+    last: Option<SystemTime>,
+}
+
+impl Default for MtimePoller {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(250),
+            last: None,
+        }
+    }
+}
+
+impl Poller for MtimePoller {
+    fn wait_for_change(&mut self, path: &Path) -> bool {
+        let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        if self.last.is_none() {
+            self.last = modified(path);
+        }
+
+        loop {
+            thread::sleep(self.interval);
+
+            let current = modified(path);
+            if current != self.last {
+                self.last = current;
+                return true;
+            }
+        }
+    }
+}
+
+pub fn watch<P, W, E>(path: &Path, poller: &mut P, mut output: W, mut error: E) -> i32
+where
+    P: Poller,
+    W: Write,
+    E: Write,
+{
+    loop {
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                eval_source(&source, &mut output, &mut error);
+            }
+            Err(err) => writeln!(error, "cannot read {}: {}", path.display(), err).unwrap(),
+        }
+
+        if !poller.wait_for_change(path) {
+            return 0;
+        }
+        writeln!(output, "{}", WATCH_SEPARATOR).unwrap();
+    }
+}
+
+// Evaluates `source` in a fresh environment, printing what `puts` writes and then
+// the final value, unless that is null. Scripts, `--watch` and `-e` all run
+// through here, so a script ending in `puts(x)` prints only what `puts` wrote.
+// Parse and runtime errors go to `error`. Returns the exit code for the run: 1 if
+// the source didn't parse or evaluated to an error, 0 otherwise.
+fn eval_source<W, E>(source: &str, mut output: W, mut error: E) -> i32
+where
+    W: Write,
+    E: Write,
+{
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        for err in parser.errors() {
+            writeln!(error, "\t{}", err).unwrap();
+        }
+        return 1;
+    }
+
+    let env = Environment::new().into();
+    let mut ctx = EvalContext::new();
+    ctx.set_output(&mut output);
+    let result = eval_program_with(program, &env, &mut ctx);
+    drop(ctx);
+
+    match result {
+        Some(evaluated @ Object::Error(_)) => {
+            writeln!(error, "{}", evaluated.inspect()).unwrap();
+            1
+        }
+        Some(Object::Null) | None => 0,
+        Some(evaluated) => {
+            writeln!(output, "{}", evaluated.inspect()).unwrap();
+            0
+        }
+    }
+}
+
+fn run_script<W, E>(path: &str, output: W, mut error: E) -> i32
+where
+    W: Write,
+    E: Write,
+{
+    match fs::read_to_string(path) {
+        Ok(source) => eval_source(&source, output, error),
+        Err(err) => {
+            writeln!(error, "cannot read {}: {}", path, err).unwrap();
+            1
+        }
+    }
+}
+
+fn run_check<W, E>(args: &[String], mut output: W, mut error: E) -> i32
+where
+    W: Write,
+    E: Write,
+{
+    let mut json = false;
+    let mut path = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--format=json" => json = true,
+            "--format=text" => json = false,
+            _ if arg.starts_with("--format=") => {
+                writeln!(error, "--format expects `text` or `json`").unwrap();
+                return 2;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                writeln!(error, "{}", USAGE).unwrap();
+                return 2;
+            }
+        }
+    }
+
+    let Some(path) = path else {
+        writeln!(error, "{}", USAGE).unwrap();
+        return 2;
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            writeln!(error, "cannot read {}: {}", path, err).unwrap();
+            return 1;
+        }
+    };
+
+    let mut parser = Parser::new(Lexer::new(&source));
+    parser.parse_program();
+    let errors = parser.errors();
+
+    if json {
+        writeln!(output, "{}", errors_to_json(&errors)).unwrap();
+    } else {
+        for err in &errors {
+            writeln!(output, "{}:{}:{}: error: {}", path, err.line, err.col, err).unwrap();
+        }
+    }
+
+    if errors.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+fn errors_to_json(errors: &[ParseError]) -> String {
+    let errors: Vec<String> = errors
+        .iter()
+        .map(|err| {
+            format!(
+                "{{\"line\":{},\"column\":{},\"severity\":\"error\",\"message\":\"{}\"}}",
+                err.line,
+                err.col,
+                json_escape(&err.message)
+            )
+        })
+        .collect();
+
+    format!("[{}]", errors.join(","))
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn run_args(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut output, mut error) = (vec![], vec![]);
+        let code = run(&args, "".as_bytes(), &mut output, &mut error);
+
+        (
+            code,
+            String::from_utf8(output).unwrap(),
+            String::from_utf8(error).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_fmt_indent_flag() {
+        let dir = TempDir::new("fmt_indent_flag");
+        let path = dir.path("script.mmn");
+        fs::write(&path, "if (x) { let y = fn() { 1 }; }").unwrap();
+        let path = path.to_str().unwrap();
+
+        assert_eq!(
+            (
+                0,
+                "if (x) {\n    let y = fn() {\n        1;\n    };\n}\n".to_string(),
+                String::new()
+            ),
+            run_args(&["fmt", "--indent", "4", path])
+        );
+        assert_eq!(
+            (
+                0,
+                "if (x) {\n\tlet y = fn() {\n\t\t1;\n\t};\n}\n".to_string(),
+                String::new()
+            ),
+            run_args(&["fmt", path, "--indent", "tab"])
+        );
+        assert_eq!(2, run_args(&["fmt", "--indent", "wide", path]).0);
+    }
+
+    #[test]
+    fn test_fmt_errors() {
+        assert_eq!(2, run_args(&["fmt"]).0);
+        assert_eq!(1, run_args(&["fmt", "/nonexistent/maymun.mmn"]).0);
+    }
+
+    #[test]
+    fn test_eval_flag() {
+        assert_eq!(
+            (0, "9\n".to_string(), String::new()),
+            run_args(&["-e", "puts(3 * 3)"])
+        );
+        assert_eq!(
+            (0, "3\n".to_string(), String::new()),
+            run_args(&["-e", "1 + 2"])
+        );
+        assert_eq!(
+            (
+                1,
+                String::new(),
+                "\texpected next token to be Assign, got Int(5) instead\n".to_string()
+            ),
+            run_args(&["-e", "let x 5;"])
+        );
+        assert_eq!(
+            (
+                1,
+                String::new(),
+                "ERROR: line 1: identifier not found: y\n".to_string()
+            ),
+            run_args(&["-e", "y"])
+        );
+        assert_eq!(2, run_args(&["-e"]).0);
+    }
+
+    #[test]
+    fn test_program_from_input() {
+        let (mut output, mut error) = (vec![], vec![]);
+        let code = run(
+            &["-".to_string()],
+            "let x = 2;\nputs(x + 1);\nx * 10\n".as_bytes(),
+            &mut output,
+            &mut error,
+        );
+
+        assert_eq!(0, code);
+        assert_eq!("3\n20\n", String::from_utf8(output).unwrap());
+        assert_eq!("", String::from_utf8(error).unwrap());
+    }
+
+    #[test]
+    fn test_check_json() {
+        let dir = TempDir::new("check_json");
+        let path = dir.path("script.mmn");
+        fs::write(&path, "let x 1;\n\n  let y = )").unwrap();
+        let path = path.to_str().unwrap();
+
+        let (code, output, error) = run_args(&["--check", "--format=json", path]);
+
+        assert_eq!(1, code);
+        assert_eq!("", error);
+        assert_eq!(
+            concat!(
+                "[{\"line\":1,\"column\":7,\"severity\":\"error\",",
+                "\"message\":\"expected next token to be Assign, got Int(1) instead\"},",
+                "{\"line\":3,\"column\":11,\"severity\":\"error\",",
+                "\"message\":\"undefined expression for ) found\"}]\n"
+            ),
+            output
+        );
+    }
+
+    #[test]
+    fn test_check_clean_file() {
+        let dir = TempDir::new("check_clean_file");
+        let path = dir.path("script.mmn");
+        fs::write(&path, "let s = \"a\\\\b\";").unwrap();
+        let path = path.to_str().unwrap();
+
+        assert_eq!(
+            (0, "[]\n".to_string(), String::new()),
+            run_args(&["--check", "--format=json", path])
+        );
+        assert_eq!(
+            (0, String::new(), String::new()),
+            run_args(&["--check", path])
+        );
+        assert_eq!(2, run_args(&["--check", "--format=xml", path]).0);
+    }
+
+    #[test]
+    fn test_json_escape() {
+        assert_eq!("a\\\"b\\\\c\\n", json_escape("a\"b\\c\n"));
+    }
+
+    struct ScriptedPoller {
+        edits: Vec<&'static str>,
+    }
+
+    impl Poller for ScriptedPoller {
+        fn wait_for_change(&mut self, path: &Path) -> bool {
+            match self.edits.pop() {
+                Some(source) => {
+                    fs::write(path, source).unwrap();
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    #[test]
+    fn test_watch_reruns_on_change() {
+        let dir = TempDir::new("watch_reruns_on_change");
+        let path = dir.path("script.mmn");
+        fs::write(&path, "let x = 5; x + 1").unwrap();
+
+        let mut poller = ScriptedPoller {
+            edits: vec!["x", "let x 1;", "let x = 10; x * 2"],
+        };
+        let (mut output, mut error) = (vec![], vec![]);
+        let code = watch(&path, &mut poller, &mut output, &mut error);
+
+        assert_eq!(0, code);
+        assert_eq!(
+            "6\n----\n20\n----\n----\n",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(
+            "\texpected next token to be Assign, got Int(1) instead\nERROR: line 1: identifier not found: x\n",
+            String::from_utf8(error).unwrap()
+        );
+    }
+}
@@ -0,0 +1,1046 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::{checked_integer, eval_infix_expression, is_truthy};
+use crate::object::{Builtin, BuiltinContext, HashKey, HashPairs, Object, NULL};
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "abs",
+        func: abs,
+    },
+    Builtin {
+        name: "assert",
+        func: assert,
+    },
+    Builtin {
+        name: "assert_eq",
+        func: assert_eq,
+    },
+    Builtin {
+        name: "bool",
+        func: bool,
+    },
+    Builtin {
+        name: "chars",
+        func: chars,
+    },
+    Builtin {
+        name: "contains",
+        func: contains,
+    },
+    Builtin {
+        name: "copy",
+        func: copy,
+    },
+    Builtin {
+        name: "delete",
+        func: delete,
+    },
+    Builtin {
+        name: "filter",
+        func: filter,
+    },
+    Builtin {
+        name: "int",
+        func: int,
+    },
+    Builtin {
+        name: "join",
+        func: join,
+    },
+    Builtin {
+        name: "keys",
+        func: keys,
+    },
+    Builtin {
+        name: "len",
+        func: len,
+    },
+    Builtin {
+        name: "lower",
+        func: lower,
+    },
+    Builtin {
+        name: "map",
+        func: map,
+    },
+    Builtin {
+        name: "max",
+        func: max,
+    },
+    Builtin {
+        name: "min",
+        func: min,
+    },
+    Builtin {
+        name: "print",
+        func: print,
+    },
+    Builtin {
+        name: "puts",
+        func: puts,
+    },
+    Builtin {
+        name: "range",
+        func: range,
+    },
+    Builtin {
+        name: "reduce",
+        func: reduce,
+    },
+    Builtin {
+        name: "replace",
+        func: replace,
+    },
+    Builtin {
+        name: "reverse",
+        func: reverse,
+    },
+    Builtin {
+        name: "slice",
+        func: slice,
+    },
+    Builtin {
+        name: "sort",
+        func: sort,
+    },
+    Builtin {
+        name: "split",
+        func: split,
+    },
+    Builtin {
+        name: "str",
+        func: str,
+    },
+    Builtin {
+        name: "trim",
+        func: trim,
+    },
+    Builtin {
+        name: "type",
+        func: type_of,
+    },
+    Builtin {
+        name: "upper",
+        func: upper,
+    },
+    Builtin {
+        name: "values",
+        func: values,
+    },
+];
+
+// Infix operators wrapped as two-argument builtins, e.g. `(+)`.
+const OPERATORS: &[Builtin] = &[
+    Builtin {
+        name: "+",
+        func: add,
+    },
+    Builtin {
+        name: "-",
+        func: subtract,
+    },
+    Builtin {
+        name: "*",
+        func: multiply,
+    },
+    Builtin {
+        name: "/",
+        func: divide,
+    },
+    Builtin {
+        name: "<",
+        func: less_than,
+    },
+    Builtin {
+        name: ">",
+        func: greater_than,
+    },
+    Builtin {
+        name: "<=",
+        func: less_equal,
+    },
+    Builtin {
+        name: ">=",
+        func: greater_equal,
+    },
+    Builtin {
+        name: "==",
+        func: equal,
+    },
+    Builtin {
+        name: "!=",
+        func: not_equal,
+    },
+    Builtin {
+        name: "&",
+        func: bit_and,
+    },
+    Builtin {
+        name: "|",
+        func: bit_or,
+    },
+    Builtin {
+        name: "^",
+        func: bit_xor,
+    },
+    Builtin {
+        name: "<<",
+        func: shift_left,
+    },
+    Builtin {
+        name: ">>",
+        func: shift_right,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<Object> {
+    BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == name)
+        .map(|builtin| Object::Builtin(*builtin))
+}
+
+pub fn names() -> Vec<&'static str> {
+    BUILTINS.iter().map(|builtin| builtin.name).collect()
+}
+
+pub fn lookup_operator(op: &str) -> Option<Object> {
+    OPERATORS
+        .iter()
+        .find(|builtin| builtin.name == op)
+        .map(|builtin| Object::Builtin(*builtin))
+}
+
+fn wrong_number_of_arguments(expected: usize, got: usize) -> Object {
+    Object::Error(format!(
+        "wrong number of arguments: expected {}, got {}",
+        expected, got
+    ))
+}
+
+fn abs(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([Object::Integer(i)]) => checked_integer(i.checked_abs()),
+        Ok([Object::Float(f)]) => Object::Float(f.abs()),
+        Ok([other]) => Object::Error(format!(
+            "argument to `abs` must be a number, got {}",
+            other.type_name()
+        )),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+fn min(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    pick_number("min", args, Ordering::Less)
+}
+
+fn max(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    pick_number("max", args, Ordering::Greater)
+}
+
+// Returns the right argument if it compares to the left as `wanted`, otherwise
+// the left one. An integer and a float are compared by value.
+fn pick_number(name: &str, args: Vec<Object>, wanted: Ordering) -> Object {
+    let [left, right] = match <[Object; 2]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    let ordering = match (&left, &right, as_float(&left), as_float(&right)) {
+        (Object::Integer(l), Object::Integer(r), _, _) => Some(r.cmp(l)),
+        (_, _, Some(l), Some(r)) => r.partial_cmp(&l),
+        _ => {
+            return Object::Error(format!(
+                "arguments to `{}` must be numbers, got {} and {}",
+                name,
+                left.type_name(),
+                right.type_name()
+            ))
+        }
+    };
+
+    if ordering == Some(wanted) {
+        right
+    } else {
+        left
+    }
+}
+
+fn as_float(object: &Object) -> Option<f64> {
+    match object {
+        Object::Integer(i) => Some(*i as f64),
+        Object::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn assert(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([cond]) if is_truthy(&cond) => NULL,
+        Ok(_) => Object::Error("assertion failed".to_string()),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+fn assert_eq(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        return wrong_number_of_arguments(2, args.len());
+    }
+
+    let (left, right) = (&args[0], &args[1]);
+    if left == right {
+        return NULL;
+    }
+
+    let mut msg = format!(
+        "assertion failed: {} != {}",
+        left.inspect(),
+        right.inspect()
+    );
+    if let Some(diff) = first_difference(left, right) {
+        msg.push_str(&format!(" ({})", diff));
+    }
+
+    Object::Error(msg)
+}
+
+// Points at the first element or key where two unequal collections diverge, so
+// a failing assert_eq on a large array or hash doesn't need to be eyeballed.
+fn first_difference(left: &Object, right: &Object) -> Option<String> {
+    match (left, right) {
+        (Object::Array(l), Object::Array(r)) => {
+            for (i, (le, re)) in l.iter().zip(r.iter()).enumerate() {
+                if le != re {
+                    return Some(format!(
+                        "first difference at index {}: {} != {}",
+                        i,
+                        le.inspect(),
+                        re.inspect()
+                    ));
+                }
+            }
+
+            Some(format!("lengths differ: {} != {}", l.len(), r.len()))
+        }
+        (Object::Hash(l), Object::Hash(r)) => {
+            let mut keys: Vec<&HashKey> = l.keys().chain(r.keys()).collect();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let name = Object::from(key.clone()).inspect();
+                match (l.get(key), r.get(key)) {
+                    (Some(lv), Some(rv)) if lv == rv => continue,
+                    (Some(lv), Some(rv)) => {
+                        return Some(format!(
+                            "first difference at key {}: {} != {}",
+                            name,
+                            lv.inspect(),
+                            rv.inspect()
+                        ))
+                    }
+                    (Some(_), None) => return Some(format!("key {} missing on the right", name)),
+                    (None, _) => return Some(format!("key {} missing on the left", name)),
+                }
+            }
+
+            None
+        }
+        _ => None,
+    }
+}
+
+fn contains(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let [collection, item] = match <[Object; 2]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    match (&collection, &item) {
+        (Object::Array(elements), _) => Object::from(elements.contains(&item)),
+        (Object::String(s), Object::String(sub)) => Object::from(s.contains(sub.as_str())),
+        (Object::String(_), _) => Object::Error(format!(
+            "can only search a string for a string, got {}",
+            item.type_name()
+        )),
+        (Object::Hash(pairs), _) => match item.hash_key() {
+            Some(key) => Object::from(pairs.contains_key(&key)),
+            None => Object::Error(format!("unusable as hash key: {}", item.type_name())),
+        },
+        _ => Object::Error(format!(
+            "first argument to `contains` must be an array, a string or a hash, got {}",
+            collection.type_name()
+        )),
+    }
+}
+
+// `keys` and `values` list a hash's entries in insertion order, the order the
+// hash prints in, so the two line up.
+fn keys(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match hash_argument("keys", args) {
+        Ok(pairs) => Object::Array(Rc::new(pairs.keys().map(|k| k.clone().into()).collect())),
+        Err(err) => err,
+    }
+}
+
+fn values(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match hash_argument("values", args) {
+        Ok(pairs) => Object::Array(Rc::new(
+            Rc::unwrap_or_clone(pairs)
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect(),
+        )),
+        Err(err) => err,
+    }
+}
+
+fn hash_argument(name: &str, args: Vec<Object>) -> Result<Rc<HashPairs>, Object> {
+    match <[Object; 1]>::try_from(args) {
+        Ok([Object::Hash(pairs)]) => Ok(pairs),
+        Ok([other]) => Err(Object::Error(format!(
+            "argument to `{}` must be a hash, got {}",
+            name,
+            other.type_name()
+        ))),
+        Err(args) => Err(wrong_number_of_arguments(1, args.len())),
+    }
+}
+
+// Returns a copy of the hash without `key`; the original binding is untouched.
+fn delete(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let (mut pairs, key) = match <[Object; 2]>::try_from(args) {
+        Ok([Object::Hash(pairs), key]) => (pairs, key),
+        Ok([other, _]) => {
+            return Object::Error(format!(
+                "first argument to `delete` must be a hash, got {}",
+                other.type_name()
+            ))
+        }
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    match key.hash_key() {
+        Some(hash_key) => {
+            Rc::make_mut(&mut pairs).remove(&hash_key);
+            Object::Hash(pairs)
+        }
+        None => Object::Error(format!("unusable as hash key: {}", key.type_name())),
+    }
+}
+
+fn len(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        return wrong_number_of_arguments(1, args.len());
+    }
+
+    match &args[0] {
+        Object::String(s) => Object::Integer(s.chars().count() as i64),
+        Object::Array(elements) => Object::Integer(elements.len() as i64),
+        Object::Hash(pairs) => Object::Integer(pairs.len() as i64),
+        other => Object::Error(format!(
+            "argument to `len` not supported, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+// Strings are written without quotes; everything else as it would be inspected.
+fn puts(ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    for arg in args {
+        let result = match arg {
+            Object::String(s) => writeln!(ctx.output(), "{}", s),
+            other => writeln!(ctx.output(), "{}", other.inspect()),
+        };
+
+        if let Err(err) = result {
+            return Object::Error(format!("cannot write output: {}", err));
+        }
+    }
+
+    NULL
+}
+
+// Unlike `puts`, writes no newline and hands its argument back, so it can wrap
+// any expression.
+fn print(ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let [object] = match <[Object; 1]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_number_of_arguments(1, args.len()),
+    };
+
+    match write!(ctx.output(), "{}", object.inspect()) {
+        Ok(()) => object,
+        Err(err) => Object::Error(format!("cannot write output: {}", err)),
+    }
+}
+
+// Bounds past the end are clamped, and a string is sliced by chars rather than
+// bytes. Without an end the slice runs to the end of the sequence.
+fn slice(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    if args.len() != 2 && args.len() != 3 {
+        return Object::Error(format!(
+            "wrong number of arguments: expected 2 or 3, got {}",
+            args.len()
+        ));
+    }
+
+    let mut args = args.into_iter();
+    let seq = args.next().unwrap();
+    let mut bounds = vec![];
+    for bound in args {
+        match bound {
+            Object::Integer(i) if i >= 0 => bounds.push(i as usize),
+            Object::Integer(i) => {
+                return Object::Error(format!("slice bounds must not be negative, got {}", i))
+            }
+            other => {
+                return Object::Error(format!(
+                    "slice bounds must be integers, got {}",
+                    other.type_name()
+                ))
+            }
+        }
+    }
+
+    let range = |len: usize| {
+        let end = bounds.get(1).map_or(len, |&end| end.min(len));
+        let start = bounds[0].min(end);
+        (start, end)
+    };
+
+    match seq {
+        Object::Array(elements) => {
+            let (start, end) = range(elements.len());
+            Object::Array(elements[start..end].to_vec().into())
+        }
+        Object::String(s) => {
+            let (start, end) = range(s.chars().count());
+            Object::String(Rc::new(s.chars().skip(start).take(end - start).collect()))
+        }
+        other => Object::Error(format!(
+            "argument to `slice` must be an array or a string, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+// `range(end)`, `range(start, end)` or `range(start, end, step)`. The end is
+// excluded, and a negative step counts down.
+fn range(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let mut bounds = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg {
+            Object::Integer(i) => bounds.push(i),
+            other => {
+                return Object::Error(format!(
+                    "arguments to `range` must be integers, got {}",
+                    other.type_name()
+                ))
+            }
+        }
+    }
+
+    let (start, end, step) = match bounds[..] {
+        [end] => (0, end, 1),
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step),
+        _ => {
+            return Object::Error(format!(
+                "wrong number of arguments: expected 1 to 3, got {}",
+                bounds.len()
+            ))
+        }
+    };
+    if step == 0 {
+        return Object::Error("range step must not be zero".to_string());
+    }
+
+    let mut elements = vec![];
+    let mut i = start;
+    while (step > 0 && i < end) || (step < 0 && i > end) {
+        elements.push(Object::Integer(i));
+        i = match i.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    Object::Array(elements.into())
+}
+
+// Strings are reversed by char, so multi-byte characters stay intact.
+fn reverse(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([Object::Array(mut elements)]) => {
+            Rc::make_mut(&mut elements).reverse();
+            Object::Array(elements)
+        }
+        Ok([Object::String(s)]) => Object::String(Rc::new(s.chars().rev().collect())),
+        Ok([other]) => Object::Error(format!(
+            "argument to `reverse` must be an array or a string, got {}",
+            other.type_name()
+        )),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+// Without a comparator the array must hold only integers or only strings. A
+// comparator is called as `cmp(a, b)` and returns a negative integer if `a`
+// goes first, a positive one if `b` does and zero if either order will do.
+fn sort(ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    if args.len() != 1 && args.len() != 2 {
+        return Object::Error(format!(
+            "wrong number of arguments: expected 1 or 2, got {}",
+            args.len()
+        ));
+    }
+
+    let mut args = args.into_iter();
+    let elements = match args.next().unwrap() {
+        Object::Array(elements) => Rc::unwrap_or_clone(elements),
+        other => {
+            return Object::Error(format!(
+                "first argument to `sort` must be an array, got {}",
+                other.type_name()
+            ))
+        }
+    };
+
+    let sorted = match args.next() {
+        Some(cmp) if is_callable(&cmp) => merge_sort(elements, &mut |a, b| {
+            let order = ctx.call(&cmp, vec![a.clone(), b.clone()]);
+            match order {
+                Object::Integer(i) => Ok(i.cmp(&0)),
+                err @ Object::Error(_) => Err(err),
+                other => Err(Object::Error(format!(
+                    "comparator passed to `sort` must return an integer, got {}",
+                    other.type_name()
+                ))),
+            }
+        }),
+        Some(other) => {
+            return Object::Error(format!(
+                "second argument to `sort` must be a function, got {}",
+                other.type_name()
+            ))
+        }
+        None => merge_sort(elements, &mut |a, b| match (a, b) {
+            (Object::Integer(a), Object::Integer(b)) => Ok(a.cmp(b)),
+            (Object::String(a), Object::String(b)) => Ok(a.cmp(b)),
+            (Object::Integer(_) | Object::String(_), Object::Integer(_) | Object::String(_)) => {
+                Err(Object::Error(format!(
+                    "cannot sort an array mixing {} and {}",
+                    a.type_name(),
+                    b.type_name()
+                )))
+            }
+            (Object::Integer(_) | Object::String(_), other) | (other, _) => {
+                Err(Object::Error(format!(
+                    "can only sort integers or strings without a comparator, got {}",
+                    other.type_name()
+                )))
+            }
+        }),
+    };
+
+    match sorted {
+        Ok(elements) => Object::Array(elements.into()),
+        Err(err) => err,
+    }
+}
+
+// A stable sort that stops at the first failed comparison. A user comparator
+// need not be consistent, which the standard library sorts may panic on.
+fn merge_sort(
+    mut elements: Vec<Object>,
+    cmp: &mut dyn FnMut(&Object, &Object) -> Result<Ordering, Object>,
+) -> Result<Vec<Object>, Object> {
+    if elements.len() < 2 {
+        return Ok(elements);
+    }
+
+    let right = elements.split_off(elements.len() / 2);
+    let left = merge_sort(elements, cmp)?;
+    let right = merge_sort(right, cmp)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if cmp(l, r)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+// An empty separator splits the string into its chars.
+fn split(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let (s, sep) = match <[Object; 2]>::try_from(args) {
+        Ok([Object::String(s), Object::String(sep)]) => (s, sep),
+        Ok([s, sep]) => {
+            return Object::Error(format!(
+                "arguments to `split` must be strings, got {} and {}",
+                s.type_name(),
+                sep.type_name()
+            ))
+        }
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    let pieces: Vec<Object> = if sep.is_empty() {
+        s.chars()
+            .map(|c| Object::String(c.to_string().into()))
+            .collect()
+    } else {
+        s.split(sep.as_str())
+            .map(|piece| Object::String(piece.to_string().into()))
+            .collect()
+    };
+
+    Object::Array(pieces.into())
+}
+
+fn join(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let (elements, sep) = match <[Object; 2]>::try_from(args) {
+        Ok([Object::Array(elements), Object::String(sep)]) => (elements, sep),
+        Ok([arr, sep]) => {
+            return Object::Error(format!(
+                "arguments to `join` must be an array and a string, got {} and {}",
+                arr.type_name(),
+                sep.type_name()
+            ))
+        }
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    let mut pieces = Vec::with_capacity(elements.len());
+    for element in elements.iter() {
+        match element {
+            Object::String(s) => pieces.push(s.as_str()),
+            other => {
+                return Object::Error(format!(
+                    "elements passed to `join` must be strings, got {}",
+                    other.type_name()
+                ))
+            }
+        }
+    }
+
+    Object::String(pieces.join(sep.as_str()).into())
+}
+
+// Strings, arrays and hashes are copied when either side is next changed, so
+// handing the argument back already behaves as a copy of whatever binding it was
+// read from. Integers, floats and booleans are plain values either way, and a
+// copied function still shares the scope it closed over.
+fn copy(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([object]) => object,
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+fn str(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([Object::String(s)]) => Object::String(s),
+        Ok([object]) => Object::String(object.inspect().into()),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+fn bool(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([object]) => Object::from(is_truthy(&object)),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+// Surrounding whitespace is ignored, so `int(" 42\n")` is 42.
+fn int(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([Object::Integer(i)]) => Object::Integer(i),
+        Ok([Object::Boolean(b)]) => Object::Integer(b as i64),
+        Ok([Object::String(s)]) => match s.trim().parse::<i64>() {
+            Ok(i) => Object::Integer(i),
+            Err(_) => Object::Error(format!("cannot parse \"{}\" as an integer", s)),
+        },
+        Ok([other]) => Object::Error(format!(
+            "argument to `int` not supported, got {}",
+            other.type_name()
+        )),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+fn type_of(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match <[Object; 1]>::try_from(args) {
+        Ok([object]) => Object::String(object.type_name().to_string().into()),
+        Err(args) => wrong_number_of_arguments(1, args.len()),
+    }
+}
+
+fn map(ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let [collection, function] = match <[Object; 2]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    let Object::Array(elements) = collection else {
+        return Object::Error(format!(
+            "first argument to `map` must be an array, got {}",
+            collection.type_name()
+        ));
+    };
+    if !is_callable(&function) {
+        return Object::Error(format!(
+            "second argument to `map` must be a function, got {}",
+            function.type_name()
+        ));
+    }
+
+    let mut mapped = Vec::with_capacity(elements.len());
+    for element in Rc::unwrap_or_clone(elements) {
+        match ctx.call(&function, vec![element]) {
+            err @ Object::Error(_) => return err,
+            o => mapped.push(o),
+        }
+    }
+
+    Object::Array(mapped.into())
+}
+
+fn filter(ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let [collection, predicate] = match <[Object; 2]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_number_of_arguments(2, args.len()),
+    };
+
+    let Object::Array(elements) = collection else {
+        return Object::Error(format!(
+            "first argument to `filter` must be an array, got {}",
+            collection.type_name()
+        ));
+    };
+    if !is_callable(&predicate) {
+        return Object::Error(format!(
+            "second argument to `filter` must be a function, got {}",
+            predicate.type_name()
+        ));
+    }
+
+    let mut kept = vec![];
+    for element in Rc::unwrap_or_clone(elements) {
+        match ctx.call(&predicate, vec![element.clone()]) {
+            err @ Object::Error(_) => return err,
+            keep if is_truthy(&keep) => kept.push(element),
+            _ => {}
+        }
+    }
+
+    Object::Array(kept.into())
+}
+
+fn is_callable(object: &Object) -> bool {
+    matches!(object, Object::Function(_) | Object::Builtin(_))
+}
+
+fn reduce(ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let [collection, mut accumulator, function] = match <[Object; 3]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_number_of_arguments(3, args.len()),
+    };
+
+    let Object::Array(elements) = collection else {
+        return Object::Error(format!(
+            "first argument to `reduce` must be an array, got {}",
+            collection.type_name()
+        ));
+    };
+    if !is_callable(&function) {
+        return Object::Error(format!(
+            "third argument to `reduce` must be a function, got {}",
+            function.type_name()
+        ));
+    }
+
+    for element in Rc::unwrap_or_clone(elements) {
+        accumulator = ctx.call(&function, vec![accumulator, element]);
+        if let Object::Error(_) = accumulator {
+            return accumulator;
+        }
+    }
+
+    accumulator
+}
+
+// The string builtins below change their argument in place through
+// `Rc::make_mut`, which copies it first only if something else still holds it. A
+// string produced by another call, as in `lower(trim(s))`, is held by nothing
+// else, so it is reused at every step instead of reallocated.
+fn string_argument(name: &str, args: Vec<Object>) -> Result<Rc<String>, Object> {
+    match <[Object; 1]>::try_from(args) {
+        Ok([Object::String(s)]) => Ok(s),
+        Ok([other]) => Err(Object::Error(format!(
+            "argument to `{}` must be a string, got {}",
+            name,
+            other.type_name()
+        ))),
+        Err(args) => Err(wrong_number_of_arguments(1, args.len())),
+    }
+}
+
+fn trim(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let mut s = match string_argument("trim", args) {
+        Ok(s) => s,
+        Err(err) => return err,
+    };
+
+    let leading = s.len() - s.trim_start().len();
+    let trailing = s.len() - s.trim_end().len();
+    if leading + trailing > 0 {
+        let owned = Rc::make_mut(&mut s);
+        owned.truncate(owned.len() - trailing);
+        owned.drain(..leading);
+    }
+
+    Object::String(s)
+}
+
+fn lower(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match string_argument("lower", args) {
+        Ok(mut s) if s.is_ascii() => {
+            Rc::make_mut(&mut s).make_ascii_lowercase();
+            Object::String(s)
+        }
+        Ok(s) => Object::String(s.to_lowercase().into()),
+        Err(err) => err,
+    }
+}
+
+fn upper(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match string_argument("upper", args) {
+        Ok(mut s) if s.is_ascii() => {
+            Rc::make_mut(&mut s).make_ascii_uppercase();
+            Object::String(s)
+        }
+        Ok(s) => Object::String(s.to_uppercase().into()),
+        Err(err) => err,
+    }
+}
+
+fn chars(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    match string_argument("chars", args) {
+        Ok(s) => Object::Array(Rc::new(
+            s.chars()
+                .map(|c| Object::String(c.to_string().into()))
+                .collect(),
+        )),
+        Err(err) => err,
+    }
+}
+
+fn replace(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    let (mut s, from, to) = match <[Object; 3]>::try_from(args) {
+        Ok([Object::String(s), Object::String(from), Object::String(to)]) => (s, from, to),
+        Ok(args) => {
+            return Object::Error(format!(
+                "arguments to `replace` must be strings, got {}, {}, {}",
+                args[0].type_name(),
+                args[1].type_name(),
+                args[2].type_name()
+            ))
+        }
+        Err(args) => return wrong_number_of_arguments(3, args.len()),
+    };
+
+    if from.is_empty() || from.len() != to.len() {
+        return Object::String(s.replace(from.as_str(), &to).into());
+    }
+
+    // Same-length replacements never move the rest of the string, so they can
+    // overwrite each match where it stands.
+    let mut start = 0;
+    while let Some(offset) = s[start..].find(from.as_str()) {
+        let at = start + offset;
+        Rc::make_mut(&mut s).replace_range(at..at + from.len(), &to);
+        start = at + to.len();
+    }
+
+    Object::String(s)
+}
+
+fn binary_operator(op: &str, args: Vec<Object>) -> Object {
+    match <[Object; 2]>::try_from(args) {
+        Ok([left, right]) => eval_infix_expression(op, left, right),
+        Err(args) => wrong_number_of_arguments(2, args.len()),
+    }
+}
+
+fn add(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("+", args)
+}
+
+fn subtract(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("-", args)
+}
+
+fn multiply(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("*", args)
+}
+
+fn divide(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("/", args)
+}
+
+fn less_than(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("<", args)
+}
+
+fn greater_than(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator(">", args)
+}
+
+fn less_equal(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("<=", args)
+}
+
+fn greater_equal(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator(">=", args)
+}
+
+fn equal(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("==", args)
+}
+
+fn not_equal(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("!=", args)
+}
+
+fn bit_and(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("&", args)
+}
+
+fn bit_or(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("|", args)
+}
+
+fn bit_xor(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("^", args)
+}
+
+fn shift_left(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator("<<", args)
+}
+
+fn shift_right(_ctx: &mut dyn BuiltinContext, args: Vec<Object>) -> Object {
+    binary_operator(">>", args)
+}
@@ -1,179 +1,931 @@
-use crate::ast::{Expression, Program, Statement, Statements};
-use crate::object::{Environment, Object};
+mod builtins;
 
-pub fn eval_program(program: Program, env: &mut Environment) -> Option<Object> {
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use crate::ast::{Expression, Pattern, Program, Statement, Statements};
+use crate::object::{BuiltinContext, Env, Environment, Function, HashPairs, Object, NULL};
+
+// Nested user function calls allowed before evaluation gives up with an error
+// rather than overflowing the native stack.
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+pub type Resolver<'a> = Box<dyn Fn(&str) -> Option<Object> + 'a>;
+
+// State shared by a whole evaluation that doesn't belong in any one scope.
+pub struct EvalContext<'a> {
+    resolver: Option<Resolver<'a>>,
+    output: Box<dyn Write + 'a>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Default for EvalContext<'_> {
+    fn default() -> Self {
+        Self {
+            resolver: None,
+            output: Box::new(io::stdout()),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+impl<'a> EvalContext<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Consulted for identifiers that are neither bound nor builtins; returning
+    // `None` keeps the usual "identifier not found" error.
+    pub fn set_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&str) -> Option<Object> + 'a,
+    {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    // Where `puts` and `print` write; stdout unless replaced.
+    pub fn set_output<W>(&mut self, output: W)
+    where
+        W: Write + 'a,
+    {
+        self.output = Box::new(output);
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    fn resolve(&self, name: &str) -> Option<Object> {
+        self.resolver.as_ref().and_then(|resolver| resolver(name))
+    }
+}
+
+impl BuiltinContext for EvalContext<'_> {
+    fn output(&mut self) -> &mut dyn Write {
+        &mut self.output
+    }
+
+    fn call(&mut self, function: &Object, args: Vec<Object>) -> Object {
+        apply_function(function, args, self)
+    }
+}
+
+// Names of the builtin functions, in alphabetical order.
+pub fn builtin_names() -> Vec<&'static str> {
+    builtins::names()
+}
+
+pub fn eval_program(program: Program, env: &Env) -> Option<Object> {
+    eval_program_with(program, env, &mut EvalContext::new())
+}
+
+pub fn eval_program_with(program: Program, env: &Env, ctx: &mut EvalContext) -> Option<Object> {
     let mut result = None;
-    for stmt in program.all() {
-        match stmt {
-            Statement::Expression(expr) => {
-                let eval = eval_expression(expr, env);
+    for (i, stmt) in program.all().iter().enumerate() {
+        match eval_program_statement(stmt, env, ctx) {
+            ControlFlow::Break(o) => return Some(locate_error(o, program.line(i))),
+            ControlFlow::Continue(o) => result = o,
+        }
+    }
 
-                match eval {
-                    Object::Return(o) => return Some(*o),
-                    Object::Error(msg) => return Some(Object::Error(msg)),
-                    _ => {}
-                }
+    result
+}
 
-                result = Some(eval)
+/// Evaluates a program one top-level statement at a time, keeping the
+/// environment alive between steps.
+pub struct Executor {
+    program: Program,
+    index: usize,
+    env: Env,
+    ctx: EvalContext<'static>,
+    halted: bool,
+}
+
+impl Executor {
+    pub fn new(program: Program, env: Env) -> Self {
+        Self {
+            program,
+            index: 0,
+            env,
+            ctx: EvalContext::new(),
+            halted: false,
+        }
+    }
+
+    /// Runs the next statement. Returns `None` once the program is exhausted or
+    /// stopped by a `return` or an error, otherwise the statement's value.
+    pub fn step(&mut self) -> Option<Option<Object>> {
+        if self.halted {
+            return None;
+        }
+
+        let stmt = self.program.all().get(self.index)?;
+        let line = self.program.line(self.index);
+        self.index += 1;
+
+        match eval_program_statement(stmt, &self.env, &mut self.ctx) {
+            ControlFlow::Break(o) => {
+                self.halted = true;
+                Some(Some(locate_error(o, line)))
             }
-            Statement::Let(ident, expr) => {
-                let eval = eval_expression(expr, env);
-                if let Object::Error(msg) = eval {
-                    return Some(Object::Error(msg));
-                }
+            ControlFlow::Continue(o) => Some(o),
+        }
+    }
 
-                env.insert(ident.to_string(), eval);
-                result = None
+    pub fn is_done(&self) -> bool {
+        self.halted || self.index >= self.program.len()
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+impl Iterator for Executor {
+    type Item = Option<Object>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step()
+    }
+}
+
+// Errors are attributed to the top-level statement that was running, which for
+// an error inside a function call is the line of the outermost call.
+fn locate_error(result: Object, line: usize) -> Object {
+    match result {
+        Object::Error(msg) => Object::Error(format!("line {}: {}", line, msg)),
+        _ => result,
+    }
+}
+
+// A `return` outside any function is allowed and ends the whole program with
+// its value, the same way it would end a function body.
+fn eval_program_statement(
+    stmt: &Statement,
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> ControlFlow<Object, Option<Object>> {
+    match stmt {
+        Statement::Expression(expr) => {
+            let eval = eval_expression(expr, env, ctx);
+
+            match eval {
+                Object::Return(o) => ControlFlow::Break(*o),
+                Object::Error(msg) => ControlFlow::Break(Object::Error(msg)),
+                Object::Break | Object::Continue => ControlFlow::Break(outside_loop(&eval)),
+                _ => ControlFlow::Continue(Some(eval)),
             }
-            Statement::Return(expr) => {
-                return Some(eval_expression(expr, env));
+        }
+        Statement::Let(ident, expr) => {
+            let eval = eval_expression(expr, env, ctx);
+
+            match eval {
+                Object::Return(o) => ControlFlow::Break(*o),
+                Object::Error(msg) => ControlFlow::Break(Object::Error(msg)),
+                Object::Break | Object::Continue => ControlFlow::Break(outside_loop(&eval)),
+                _ => {
+                    env.borrow_mut().set(ident, eval);
+                    ControlFlow::Continue(None)
+                }
+            }
+        }
+        Statement::Return(expr) => match eval_expression(expr, env, ctx) {
+            Object::Return(o) => ControlFlow::Break(*o),
+            eval @ (Object::Break | Object::Continue) => ControlFlow::Break(outside_loop(&eval)),
+            eval => ControlFlow::Break(eval),
+        },
+        Statement::While(cond, body) => match eval_while_statement(cond, body, env, ctx) {
+            Some(Object::Return(o)) => ControlFlow::Break(*o),
+            Some(err) => ControlFlow::Break(err),
+            None => ControlFlow::Continue(None),
+        },
+        Statement::ForIn(ident, iterable, body) => {
+            match eval_for_in_statement(ident, iterable, body, env, ctx) {
+                Some(Object::Return(o)) => ControlFlow::Break(*o),
+                Some(err) => ControlFlow::Break(err),
+                None => ControlFlow::Continue(None),
             }
         }
+        Statement::ForC(init, cond, post, body) => {
+            match eval_c_for_statement(init, cond, post, body, env, ctx) {
+                Some(Object::Return(o)) => ControlFlow::Break(*o),
+                Some(err) => ControlFlow::Break(err),
+                None => ControlFlow::Continue(None),
+            }
+        }
+        Statement::Block(body) => match eval_scoped_block(body, env, ctx) {
+            Some(Object::Return(o)) => ControlFlow::Break(*o),
+            Some(eval @ Object::Error(_)) => ControlFlow::Break(eval),
+            Some(eval @ (Object::Break | Object::Continue)) => {
+                ControlFlow::Break(outside_loop(&eval))
+            }
+            eval => ControlFlow::Continue(eval),
+        },
+        Statement::Break => ControlFlow::Break(outside_loop(&Object::Break)),
+        Statement::Continue => ControlFlow::Break(outside_loop(&Object::Continue)),
     }
+}
 
-    result
+fn outside_loop(signal: &Object) -> Object {
+    Object::Error(format!("{} outside loop", signal.inspect()))
+}
+
+fn is_signal(object: &Object) -> bool {
+    matches!(
+        object,
+        Object::Return(_) | Object::Error(_) | Object::Break | Object::Continue
+    )
 }
 
-fn eval_block_statements(stmts: &Statements, env: &mut Environment) -> Option<Object> {
+fn eval_block_statements(stmts: &[Statement], env: &Env, ctx: &mut EvalContext) -> Option<Object> {
     let mut result = None;
     for stmt in stmts {
         match stmt {
             Statement::Expression(expr) => {
-                let eval = eval_expression(expr, env);
+                let eval = eval_expression(expr, env, ctx);
 
-                match eval {
-                    Object::Return(o) => return Some(*o),
-                    Object::Error(msg) => return Some(Object::Error(msg)),
-                    _ => {}
+                if is_signal(&eval) {
+                    return Some(eval);
                 }
 
                 result = Some(eval)
             }
+            Statement::Let(ident, expr) => {
+                let eval = eval_expression(expr, env, ctx);
+
+                if is_signal(&eval) {
+                    return Some(eval);
+                }
+
+                env.borrow_mut().set(ident, eval);
+                result = None
+            }
             Statement::Return(expr) => {
-                let eval = eval_expression(expr, env);
+                let eval = eval_expression(expr, env, ctx);
 
-                if let Object::Error(msg) = eval {
-                    return Some(Object::Error(msg));
+                if is_signal(&eval) {
+                    return Some(eval);
                 }
 
                 return Some(Object::Return(Box::new(eval)));
             }
-            _ => {}
+            Statement::While(cond, body) => {
+                if let Some(eval) = eval_while_statement(cond, body, env, ctx) {
+                    return Some(eval);
+                }
+
+                result = None
+            }
+            Statement::ForIn(ident, iterable, body) => {
+                if let Some(eval) = eval_for_in_statement(ident, iterable, body, env, ctx) {
+                    return Some(eval);
+                }
+
+                result = None
+            }
+            Statement::ForC(init, cond, post, body) => {
+                if let Some(eval) = eval_c_for_statement(init, cond, post, body, env, ctx) {
+                    return Some(eval);
+                }
+
+                result = None
+            }
+            Statement::Block(body) => {
+                let eval = eval_scoped_block(body, env, ctx);
+                if eval.as_ref().is_some_and(is_signal) {
+                    return eval;
+                }
+
+                result = eval
+            }
+            Statement::Break => return Some(Object::Break),
+            Statement::Continue => return Some(Object::Continue),
         }
     }
 
     result
 }
 
-fn eval_expression(expr: &Expression, env: &mut Environment) -> Object {
+// Runs a bare block in its own scope, so its `let`s don't outlive it.
+fn eval_scoped_block(body: &Statements, env: &Env, ctx: &mut EvalContext) -> Option<Object> {
+    let scope: Env = Environment::new_enclosed(Rc::clone(env)).into();
+    eval_block_statements(body, &scope, ctx)
+}
+
+// Yields a value only when the loop is cut short by a `return` or an error.
+fn eval_while_statement(
+    cond: &Expression,
+    body: &Statements,
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> Option<Object> {
+    loop {
+        let cond = eval_expression(cond, env, ctx);
+        if let Object::Error(_) = cond {
+            return Some(cond);
+        }
+
+        if !is_truthy(&cond) {
+            return None;
+        }
+
+        match eval_block_statements(body, env, ctx) {
+            Some(Object::Break) => return None,
+            Some(eval @ (Object::Return(_) | Object::Error(_))) => return Some(eval),
+            _ => {}
+        }
+    }
+}
+
+// Each iteration runs in its own scope holding the loop variable, so neither it
+// nor the body's `let`s outlive the loop.
+fn eval_for_in_statement(
+    ident: &str,
+    iterable: &Expression,
+    body: &Statements,
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> Option<Object> {
+    let elements = match eval_expression(iterable, env, ctx) {
+        Object::Array(elements) => Rc::unwrap_or_clone(elements),
+        err @ Object::Error(_) => return Some(err),
+        other => {
+            return Some(Object::Error(format!(
+                "for-in expects an array, got {}",
+                other.type_name()
+            )))
+        }
+    };
+
+    for element in elements {
+        let scope: Env = Environment::new_enclosed(Rc::clone(env)).into();
+        scope.borrow_mut().set(ident, element);
+
+        match eval_block_statements(body, &scope, ctx) {
+            Some(Object::Break) => return None,
+            Some(eval @ (Object::Return(_) | Object::Error(_))) => return Some(eval),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// The loop gets one scope of its own, holding whatever `init` declares, so a
+// counter made with `let` doesn't outlive the loop. `continue` still runs `post`.
+fn eval_c_for_statement(
+    init: &Statement,
+    cond: &Expression,
+    post: &Statement,
+    body: &Statements,
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> Option<Object> {
+    let scope: Env = Environment::new_enclosed(Rc::clone(env)).into();
+    if let Some(eval @ (Object::Return(_) | Object::Error(_))) =
+        eval_block_statements(std::slice::from_ref(init), &scope, ctx)
+    {
+        return Some(eval);
+    }
+
+    loop {
+        let cond = eval_expression(cond, &scope, ctx);
+        if let Object::Error(_) = cond {
+            return Some(cond);
+        }
+
+        if !is_truthy(&cond) {
+            return None;
+        }
+
+        match eval_block_statements(body, &scope, ctx) {
+            Some(Object::Break) => return None,
+            Some(eval @ (Object::Return(_) | Object::Error(_))) => return Some(eval),
+            _ => {}
+        }
+
+        if let Some(eval @ (Object::Return(_) | Object::Error(_))) =
+            eval_block_statements(std::slice::from_ref(post), &scope, ctx)
+        {
+            return Some(eval);
+        }
+    }
+}
+
+fn eval_expression(expr: &Expression, env: &Env, ctx: &mut EvalContext) -> Object {
     match expr {
         Expression::Int(i) => Object::Integer(*i),
-        Expression::Boolean(b) => Object::Boolean(*b),
+        Expression::Float(f) => Object::Float(*f),
+        Expression::String(s) => Object::String(s.clone().into()),
+        Expression::Boolean(b) => Object::from(*b),
+        Expression::Null => NULL,
         Expression::Literal(l) => {
-            if let Some(o) = env.get(l) {
+            if let Some(o) = env.borrow().get(l) {
                 return (*o).clone();
             }
 
-            return Object::Error("identifier not found: ".to_string() + l);
+            if let Some(builtin) = builtins::lookup(l) {
+                return builtin;
+            }
+
+            if let Some(o) = ctx.resolve(l) {
+                return o;
+            }
+
+            Object::Error("identifier not found: ".to_string() + l)
         }
         Expression::Prefix(op, right) => {
-            let right = eval_expression(right, env);
+            let right = eval_expression(right, env, ctx);
 
             if let Object::Error(msg) = right {
                 return Object::Error(msg);
             }
 
             match op.as_str() {
-                "!" => match right {
-                    Object::Boolean(b) => Object::Boolean(!b),
-                    Object::Integer(i) => Object::Boolean(i == 0),
-                    _ => Object::Error(format!("unknown prefix type: {}", right.to_string())),
+                "!" => Object::from(!is_truthy(&right)),
+                "~" => match right {
+                    Object::Integer(i) => Object::Integer(!i),
+                    _ => Object::Error(format!("unknown operator: ~{}", right.type_name())),
                 },
-                "-" => {
-                    if let Object::Integer(i) = right {
-                        Object::Integer(-i)
-                    } else {
-                        Object::Error(format!("unknown operator: -{}", right.to_string()))
-                    }
-                }
-                _ => Object::Error(format!("unknown operator: {}{}", op, right.to_string())),
+                "-" => match right {
+                    Object::Integer(i) => checked_integer(i.checked_neg()),
+                    Object::Float(f) => Object::Float(-f),
+                    _ => Object::Error(format!("unknown operator: -{}", right.type_name())),
+                },
+                _ => Object::Error(format!("unknown operator: {}{}", op, right.type_name())),
             }
         }
         Expression::Infix(left, op, right) => {
-            let left = eval_expression(left, env);
+            let left = eval_expression(left, env, ctx);
             if let Object::Error(msg) = left {
                 return Object::Error(msg);
             }
 
-            let right = eval_expression(right, env);
+            let right = eval_expression(right, env, ctx);
             if let Object::Error(msg) = right {
                 return Object::Error(msg);
             }
 
-            match (&left, &right) {
-                (Object::Integer(li), Object::Integer(ri)) => match op.as_str() {
-                    "+" => Object::Integer(li + ri),
-                    "-" => Object::Integer(li - ri),
-                    "*" => Object::Integer(li * ri),
-                    "/" => Object::Integer(li / ri),
-                    "<" => Object::Boolean(li < ri),
-                    ">" => Object::Boolean(li > ri),
-                    "==" => Object::Boolean(li == ri),
-                    "!=" => Object::Boolean(li != ri),
-                    _ => Object::Error(format!(
-                        "unknown operator: {} {} {}",
-                        left.to_string(),
-                        op,
-                        right.to_string()
-                    )),
-                },
-                _ => match op.as_str() {
-                    "==" => Object::Boolean(left == right),
-                    "!=" => Object::Boolean(left != right),
-                    _ => Object::Error(format!(
-                        "mismatch expression operation: {} {} {}",
-                        left.to_string(),
-                        op,
-                        right.to_string()
-                    )),
-                },
-            }
+            eval_infix_expression(op, left, right)
         }
         Expression::If(cond, conseq, alter) => {
-            let cond = eval_expression(cond, env);
+            let cond = eval_expression(cond, env, ctx);
+            if let Object::Error(msg) = cond {
+                return Object::Error(msg);
+            }
+
+            if is_truthy(&cond) {
+                eval_block_statements(conseq, env, ctx).unwrap_or(NULL)
+            } else if let Some(alter) = alter {
+                eval_block_statements(alter, env, ctx).unwrap_or(NULL)
+            } else {
+                NULL
+            }
+        }
+        Expression::Do(body) => {
+            let scope: Env = Environment::new_enclosed(Rc::clone(env)).into();
+            eval_block_statements(body, &scope, ctx).unwrap_or(NULL)
+        }
+        Expression::Match(value, arms) => {
+            let value = eval_expression(value, env, ctx);
+            if is_signal(&value) {
+                return value;
+            }
+
+            match arms
+                .iter()
+                .find(|(pattern, _)| matches_pattern(pattern, &value))
+            {
+                Some((_, body)) => eval_block_statements(body, env, ctx).unwrap_or(NULL),
+                None => NULL,
+            }
+        }
+        Expression::Ternary(cond, conseq, alter) => {
+            let cond = eval_expression(cond, env, ctx);
             if let Object::Error(msg) = cond {
                 return Object::Error(msg);
             }
 
-            match cond {
-                Object::Boolean(b) => {
-                    if b {
-                        eval_block_statements(conseq, env).unwrap()
-                    } else {
-                        if let Some(alter) = alter {
-                            eval_block_statements(alter, env).unwrap()
-                        } else {
-                            Object::Null
-                        }
-                    }
+            if is_truthy(&cond) {
+                eval_expression(conseq, env, ctx)
+            } else {
+                eval_expression(alter, env, ctx)
+            }
+        }
+        Expression::Call(function, arguments) => {
+            let function = eval_expression(function, env, ctx);
+            if let Object::Error(msg) = function {
+                return Object::Error(msg);
+            }
+
+            match eval_expressions(arguments.iter().map(|arg| &**arg), env, ctx) {
+                Ok(args) => apply_function(&function, args, ctx),
+                Err(err) => err,
+            }
+        }
+        Expression::MethodCall(receiver, method, arguments) => {
+            let receiver = eval_expression(receiver, env, ctx);
+            if let Object::Error(msg) = receiver {
+                return Object::Error(msg);
+            }
+
+            let Some(builtin) = builtins::lookup(method) else {
+                return Object::Error(format!(
+                    "unknown method: {}.{}",
+                    receiver.type_name(),
+                    method
+                ));
+            };
+
+            match eval_expressions(arguments, env, ctx) {
+                Ok(mut args) => {
+                    args.insert(0, receiver);
+                    apply_function(&builtin, args, ctx)
                 }
-                Object::Null => {
-                    if let Some(alter) = alter {
-                        eval_block_statements(alter, env).unwrap()
-                    } else {
-                        Object::Null
-                    }
+                Err(err) => err,
+            }
+        }
+        Expression::Array(elements) => match eval_expressions(elements, env, ctx) {
+            Ok(elements) => Object::Array(elements.into()),
+            Err(err) => err,
+        },
+        Expression::Index(left, index) => {
+            // Indexing a bound array only copies the element, not the array.
+            let left = match &**left {
+                Expression::Literal(name) => env.borrow().get(name),
+                _ => None,
+            }
+            .unwrap_or_else(|| Rc::new(eval_expression(left, env, ctx)));
+            if let Object::Error(msg) = &*left {
+                return Object::Error(msg.clone());
+            }
+
+            let index = eval_expression(index, env, ctx);
+            if let Object::Error(msg) = index {
+                return Object::Error(msg);
+            }
+
+            eval_index_expression(&left, index)
+        }
+        Expression::Hash(pairs) => eval_hash_literal(pairs, env, ctx),
+        Expression::Function(parameters, rest, body) => Object::Function(Function {
+            parameters: parameters.clone(),
+            rest: rest.clone(),
+            body: Rc::clone(body),
+            env: Rc::clone(env),
+        }),
+        Expression::OperatorFunction(op) => builtins::lookup_operator(op)
+            .unwrap_or_else(|| Object::Error(format!("unknown operator: ({})", op))),
+        Expression::Assign(name, value) => {
+            let value = eval_expression(value, env, ctx);
+
+            if is_signal(&value) {
+                return value;
+            }
+
+            if env.borrow_mut().assign(name, value.clone()) {
+                value
+            } else {
+                Object::Error(format!("assignment to undeclared variable: {}", name))
+            }
+        }
+        Expression::IndexAssign(name, index, value) => {
+            let index = eval_expression(index, env, ctx);
+            if is_signal(&index) {
+                return index;
+            }
+
+            let value = eval_expression(value, env, ctx);
+            if is_signal(&value) {
+                return value;
+            }
+
+            let assigned = env
+                .borrow_mut()
+                .update(name, |target| assign_index(target, index, value.clone()));
+            match assigned {
+                Some(Ok(())) => value,
+                Some(Err(err)) => err,
+                None => Object::Error(format!("assignment to undeclared variable: {}", name)),
+            }
+        }
+        Expression::CompoundIndexAssign(name, index, op, value) => {
+            eval_compound_index_assign(name, index, op, value, env, ctx)
+        }
+    }
+}
+
+// Reads, combines and writes the element in one pass over the binding, so
+// `a[f()] += 1` calls `f` once.
+fn eval_compound_index_assign(
+    name: &str,
+    index: &Expression,
+    op: &str,
+    value: &Expression,
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> Object {
+    let index = eval_expression(index, env, ctx);
+    if is_signal(&index) {
+        return index;
+    }
+
+    let value = eval_expression(value, env, ctx);
+    if is_signal(&value) {
+        return value;
+    }
+
+    let assigned = env.borrow_mut().update(name, |target| {
+        let current = eval_index_expression(target, index.clone());
+        let combined = match current {
+            Object::Error(_) => current,
+            _ => eval_infix_expression(op, current, value),
+        };
+        if let Object::Error(_) = combined {
+            return Err(combined);
+        }
+
+        assign_index(target, index, combined.clone()).map(|()| combined)
+    });
+    match assigned {
+        Some(Ok(combined)) => combined,
+        Some(Err(err)) => err,
+        None => Object::Error(format!("assignment to undeclared variable: {}", name)),
+    }
+}
+
+fn eval_hash_literal(
+    pairs: &[(Expression, Expression)],
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> Object {
+    let mut hash = HashPairs::new();
+    for (key, value) in pairs {
+        let key = eval_expression(key, env, ctx);
+        if let Object::Error(msg) = key {
+            return Object::Error(msg);
+        }
+
+        let Some(hash_key) = key.hash_key() else {
+            return Object::Error(format!("unusable as hash key: {}", key.type_name()));
+        };
+
+        let value = eval_expression(value, env, ctx);
+        if let Object::Error(msg) = value {
+            return Object::Error(msg);
+        }
+
+        hash.insert(hash_key, value);
+    }
+
+    Object::Hash(hash.into())
+}
+
+// A negative index counts back from the end of the array, so `a[-1]` is its
+// last element. Indices still out of range yield null, as do missing hash keys.
+fn eval_index_expression(left: &Object, index: Object) -> Object {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let i = if i < 0 { i + elements.len() as i64 } else { i };
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| elements.get(i))
+                .cloned()
+                .unwrap_or(NULL)
+        }
+        (Object::Hash(pairs), key) => match key.hash_key() {
+            Some(hash_key) => pairs.get(&hash_key).cloned().unwrap_or(NULL),
+            None => Object::Error(format!("unusable as hash key: {}", key.type_name())),
+        },
+        (left, index) => Object::Error(format!(
+            "index operator not supported: {}[{}]",
+            left.type_name(),
+            index.type_name()
+        )),
+    }
+}
+
+// Indices are resolved as in `eval_index_expression`, except that an array
+// index out of range is an error rather than null.
+fn assign_index(target: &mut Object, index: Object, value: Object) -> Result<(), Object> {
+    match (target, index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let elements = Rc::make_mut(elements);
+            let len = elements.len();
+            let resolved = if i < 0 { i + len as i64 } else { i };
+            match usize::try_from(resolved)
+                .ok()
+                .and_then(|i| elements.get_mut(i))
+            {
+                Some(element) => {
+                    *element = value;
+                    Ok(())
                 }
-                _ => eval_block_statements(conseq, env).unwrap(),
+                None => Err(Object::Error(format!(
+                    "index out of range: {} for an array of length {}",
+                    i, len
+                ))),
+            }
+        }
+        (Object::Hash(pairs), key) => match key.hash_key() {
+            Some(hash_key) => {
+                Rc::make_mut(pairs).insert(hash_key, value);
+                Ok(())
+            }
+            None => Err(Object::Error(format!(
+                "unusable as hash key: {}",
+                key.type_name()
+            ))),
+        },
+        (target, index) => Err(Object::Error(format!(
+            "index assignment not supported: {}[{}]",
+            target.type_name(),
+            index.type_name()
+        ))),
+    }
+}
+
+fn eval_expressions<'e>(
+    exprs: impl IntoIterator<Item = &'e Expression>,
+    env: &Env,
+    ctx: &mut EvalContext,
+) -> Result<Vec<Object>, Object> {
+    let mut result = vec![];
+    for expr in exprs {
+        let eval = eval_expression(expr, env, ctx);
+        if let Object::Error(msg) = eval {
+            return Err(Object::Error(msg));
+        }
+
+        result.push(eval);
+    }
+
+    Ok(result)
+}
+
+// Borrows the callee so builtins like `map` can apply one function many times
+// without copying it for every call.
+fn apply_function(function: &Object, args: Vec<Object>, ctx: &mut EvalContext) -> Object {
+    match function {
+        Object::Function(function) => {
+            if ctx.depth >= ctx.max_depth {
+                return Object::Error("maximum recursion depth exceeded".to_string());
+            }
+
+            let expected = function.parameters.len();
+            if function.rest.is_some() && args.len() < expected {
+                return Object::Error(format!(
+                    "wrong number of arguments: expected at least {}, got {}",
+                    expected,
+                    args.len()
+                ));
+            }
+            if function.rest.is_none() && args.len() != expected {
+                return Object::Error(format!(
+                    "wrong number of arguments: expected {}, got {}",
+                    expected,
+                    args.len()
+                ));
+            }
+
+            let env: Env = Environment::new_enclosed(Rc::clone(&function.env)).into();
+            let mut args = args.into_iter();
+            for (param, arg) in function.parameters.iter().zip(args.by_ref()) {
+                env.borrow_mut().set(param, arg);
+            }
+            if let Some(rest) = &function.rest {
+                env.borrow_mut()
+                    .set(rest, Object::Array(Rc::new(args.collect())));
+            }
+
+            ctx.depth += 1;
+            let result = eval_block_statements(&function.body, &env, ctx);
+            ctx.depth -= 1;
+
+            match result {
+                Some(Object::Return(o)) => *o,
+                Some(signal @ (Object::Break | Object::Continue)) => outside_loop(&signal),
+                Some(o) => o,
+                None => NULL,
             }
         }
-        _ => Object::Null,
+        Object::Builtin(builtin) => (builtin.func)(ctx, args),
+        _ => Object::Error(format!("not a function: {}", function.type_name())),
+    }
+}
+
+fn matches_pattern(pattern: &Pattern, value: &Object) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Int(p), Object::Integer(i)) => p == i,
+        (Pattern::Boolean(p), Object::Boolean(b)) => p == b,
+        (Pattern::String(p), Object::String(s)) => **s == *p,
+        _ => false,
+    }
+}
+
+// Only `false` and `null` are falsy; `0`, `""` and `[]` all count as true.
+fn is_truthy(object: &Object) -> bool {
+    !matches!(object, Object::Boolean(false) | Object::Null)
+}
+
+fn eval_infix_expression(op: &str, left: Object, right: Object) -> Object {
+    match (&left, &right) {
+        (Object::Integer(li), Object::Integer(ri)) => match op {
+            "+" => checked_integer(li.checked_add(*ri)),
+            "-" => checked_integer(li.checked_sub(*ri)),
+            "*" => checked_integer(li.checked_mul(*ri)),
+            "/" if *ri == 0 => Object::Error("division by zero".to_string()),
+            "/" => checked_integer(li.checked_div(*ri)),
+            "<" => Object::from(li < ri),
+            ">" => Object::from(li > ri),
+            "<=" => Object::from(li <= ri),
+            ">=" => Object::from(li >= ri),
+            "==" => Object::from(li == ri),
+            "!=" => Object::from(li != ri),
+            "&" => Object::Integer(li & ri),
+            "|" => Object::Integer(li | ri),
+            "^" => Object::Integer(li ^ ri),
+            "<<" => eval_shift(*li, *ri, i64::checked_shl),
+            ">>" => eval_shift(*li, *ri, i64::checked_shr),
+            _ => unknown_infix_operator(&left, op, &right),
+        },
+        (Object::String(ls), Object::String(rs)) => match op {
+            "+" => Object::String(format!("{}{}", ls, rs).into()),
+            "<" => Object::from(ls < rs),
+            ">" => Object::from(ls > rs),
+            "<=" => Object::from(ls <= rs),
+            ">=" => Object::from(ls >= rs),
+            "==" => Object::from(ls == rs),
+            "!=" => Object::from(ls != rs),
+            _ => unknown_infix_operator(&left, op, &right),
+        },
+        (Object::Float(lf), Object::Float(rf)) => eval_float_infix(op, *lf, *rf),
+        (Object::Integer(li), Object::Float(rf)) => eval_float_infix(op, *li as f64, *rf),
+        (Object::Float(lf), Object::Integer(ri)) => eval_float_infix(op, *lf, *ri as f64),
+        _ => match op {
+            "==" => Object::from(left == right),
+            "!=" => Object::from(left != right),
+            _ if left.type_name() != right.type_name() => Object::Error(format!(
+                "type mismatch: {} {} {}",
+                left.type_name(),
+                op,
+                right.type_name()
+            )),
+            _ => unknown_infix_operator(&left, op, &right),
+        },
+    }
+}
+
+fn unknown_infix_operator(left: &Object, op: &str, right: &Object) -> Object {
+    Object::Error(format!(
+        "unknown operator: {} {} {}",
+        left.type_name(),
+        op,
+        right.type_name()
+    ))
+}
+
+fn eval_float_infix(op: &str, left: f64, right: f64) -> Object {
+    match op {
+        "+" => Object::Float(left + right),
+        "-" => Object::Float(left - right),
+        "*" => Object::Float(left * right),
+        "/" => Object::Float(left / right),
+        "<" => Object::from(left < right),
+        ">" => Object::from(left > right),
+        "<=" => Object::from(left <= right),
+        ">=" => Object::from(left >= right),
+        "==" => Object::from(left == right),
+        "!=" => Object::from(left != right),
+        _ => unknown_infix_operator(&Object::Float(left), op, &Object::Float(right)),
+    }
+}
+
+fn eval_shift(value: i64, amount: i64, shift: fn(i64, u32) -> Option<i64>) -> Object {
+    match u32::try_from(amount)
+        .ok()
+        .and_then(|amount| shift(value, amount))
+    {
+        Some(i) => Object::Integer(i),
+        None => Object::Error(format!("shift amount out of range: {}", amount)),
+    }
+}
+
+fn checked_integer(result: Option<i64>) -> Object {
+    match result {
+        Some(i) => Object::Integer(i),
+        None => Object::Error("integer overflow".to_string()),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::lexer::Lexer;
+    use crate::object::{HashKey, FALSE, TRUE};
     use crate::parser::Parser;
 
     use super::*;
@@ -196,6 +948,22 @@ mod tests {
             ("3 * 3 * 3 + 10", 37),
             ("3 * (3 * 3) + 10", 37),
             ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+            ("9223372036854775806 + 1", i64::MAX),
+            ("-9223372036854775807 - 1", i64::MIN),
+            ("4611686018427387903 * 2", 9223372036854775806),
+            ("~0", -1),
+            ("~5", -6),
+            ("~~7", 7),
+            ("~-1 * 2", 0),
+            ("6 & 3", 2),
+            ("6 | 3", 7),
+            ("6 ^ 3", 5),
+            ("1 << 4", 16),
+            ("256 >> 4", 16),
+            ("-16 >> 2", -4),
+            ("1 << 2 + 1", 8),
+            ("5 & 3 + 1", 4),
+            ("(^)(12, 10)", 6),
         ];
 
         for (input, expect) in tests {
@@ -205,14 +973,46 @@ mod tests {
     }
 
     #[test]
-    fn test_eval_boolean_expression() {
+    fn test_eval_float_expression() {
         let tests = vec![
-            ("true", true),
-            ("false", false),
+            ("2.75", 2.75),
+            ("-2.5", -2.5),
+            ("1.5 + 2.5", 4.0),
+            ("3 + 0.5", 3.5),
+            ("0.5 + 3", 3.5),
+            ("10 / 4.0", 2.5),
+            ("2.0 * 3", 6.0),
+            ("1.25 - 0.25", 1.0),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::Float(expect), test_eval(input));
+        }
+
+        let tests = vec![
+            ("1.5 < 2", true),
+            ("2.5 > 2.5", false),
+            ("1.0 == 1", true),
+            ("0.1 != 0.1", false),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::from(expect), test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = vec![
+            ("true", true),
+            ("false", false),
             ("1 < 2", true),
             ("1 > 2", false),
             ("1 < 1", false),
             ("1 > 1", false),
+            ("1 <= 1", true),
+            ("1 >= 2", false),
+            ("2.5 >= 2", true),
             ("1 == 1", true),
             ("1 != 1", false),
             ("1 == 2", false),
@@ -232,6 +1032,12 @@ mod tests {
             ("!!true", true),
             ("!!false", false),
             ("!!5", true),
+            // `!x` is `!is_truthy(x)`, so it agrees with `if`: zero is truthy.
+            ("!0", false),
+            ("!!0", true),
+            ("!\"\"", false),
+            ("![]", false),
+            ("!if (false) { 1 }", true),
         ];
 
         for (input, expect) in tests {
@@ -240,6 +1046,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_equality() {
+        let tests = vec![
+            ("[1, 2] == [1, 2]", true),
+            ("[1, 2] != [1, 2]", false),
+            ("[1, 2] == [2, 1]", false),
+            ("[1, [\"a\"]] == [1, [\"a\"]]", true),
+            ("[] == []", true),
+            ("[1] == [1, 1]", false),
+            ("\"a\" == \"a\"", true),
+            ("\"a\" == \"b\"", false),
+            ("\"a\" != \"b\"", true),
+            ("{\"k\": 1} == {\"k\": 1}", true),
+            ("{\"k\": 1} == {\"k\": 2}", false),
+            ("1 == \"1\"", false),
+            ("1 != \"1\"", true),
+            ("[1] == \"[1]\"", false),
+            ("true == 1", false),
+            ("if (false) { 1 } == 0", false),
+            ("let f = fn() { 1 }; f == f", true),
+            ("fn() { 1 } == fn() { 1 }", false),
+            ("null == null", true),
+            ("null != null", false),
+            ("null == false", false),
+            ("if (false) { 1 } == null", true),
+            ("let x = null; x == null", true),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::from(expect), test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_boolean_and_null_singletons() {
+        let tests = vec![
+            ("true", TRUE),
+            ("false", FALSE),
+            ("1 < 2", TRUE),
+            ("!true", FALSE),
+            ("true == true", TRUE),
+            ("if (false) { 10 }", NULL),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+
+        assert_eq!(TRUE, Object::from(true));
+        assert_eq!(FALSE, Object::from(false));
+    }
+
     #[test]
     fn test_if_else_expressions() {
         let tests = vec![
@@ -262,6 +1120,111 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ternary_expressions() {
+        let tests = vec![
+            ("true ? 1 : 2", Object::Integer(1)),
+            ("false ? 1 : 2", Object::Integer(2)),
+            ("0 ? 1 : 2", Object::Integer(1)),
+            ("let x = -5; x > 0 ? 1 : -1", Object::Integer(-1)),
+            (
+                "let x = 0; x < 0 ? \"neg\" : x == 0 ? \"zero\" : \"pos\"",
+                Object::String("zero".to_string().into()),
+            ),
+            ("(1 < 2 ? 10 : 20) + 1", Object::Integer(11)),
+            ("true ? 1 : -true", Object::Integer(1)),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_top_level_return() {
+        let tests = vec![
+            ("return 5;", Object::Integer(5)),
+            ("let x = do { return 5; }; 6", Object::Integer(5)),
+            ("while (true) { return 5; }", Object::Integer(5)),
+            ("for (x in [1, 2]) { return x; }", Object::Integer(1)),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input), "{}", input);
+        }
+
+        let env: Env = Environment::new().into();
+        let program = Parser::new(Lexer::new("let a = 1; return 5; let a = 2;")).parse_program();
+        assert_eq!(Some(Object::Integer(5)), eval_program(program, &env));
+        assert_eq!(Some(Rc::new(Object::Integer(1))), env.borrow().get("a"));
+    }
+
+    #[test]
+    fn test_match_expressions() {
+        let describe = "let describe = fn(n) { match (n) { 0 => { \"zero\" }, 1 => { \"one\" }, -1 => { \"minus one\" }, _ => { \"many\" } } };";
+        let tests = vec![
+            (format!("{} describe(0)", describe), "\"zero\""),
+            (format!("{} describe(1)", describe), "\"one\""),
+            (format!("{} describe(-1)", describe), "\"minus one\""),
+            (format!("{} describe(7)", describe), "\"many\""),
+            (format!("{} describe(\"1\")", describe), "\"many\""),
+            (
+                "match (1 < 2) { false => { 1 }, true => { 2 } }".to_string(),
+                "2",
+            ),
+            (
+                "match (\"b\") { \"a\" => { 1 }, \"b\" => { 2 } }".to_string(),
+                "2",
+            ),
+            ("match (3) { 1 => { 1 } }".to_string(), "null"),
+            ("match (1) { _ => { 1 }, 1 => { 2 } }".to_string(), "1"),
+            (
+                "match (x) { _ => { 1 } }".to_string(),
+                "ERROR: line 1: identifier not found: x",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(&input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_do_expressions() {
+        let tests = vec![
+            ("let x = do { let a = 1; a + 1 }; x", "2"),
+            (
+                "let x = do { let a = 1; a + 1 }; a",
+                "ERROR: line 1: identifier not found: a",
+            ),
+            ("let a = 1; do { let a = 2; }; a", "1"),
+            ("let a = 1; do { a = 2; }; a", "2"),
+            ("do {}", "null"),
+            ("let f = fn() { do { return 1; }; 2 }; f()", "1"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_block_statements() {
+        let tests = vec![
+            ("{ let x = 1; } x", "ERROR: line 1: identifier not found: x"),
+            ("let x = 1; { let x = 2; } x", "1"),
+            ("let x = 1; { x = 2; } x", "2"),
+            ("{ let x = 1; x + 1 }", "2"),
+            ("let f = fn() { { return 1; } 2 }; f()", "1"),
+            ("let i = 0; while (true) { { i += 1; break; } } i", "1"),
+            ("{ break; }", "ERROR: line 1: break outside loop"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
     #[test]
     fn test_return_statement() {
         let tests = vec![
@@ -282,34 +1245,85 @@ mod tests {
     }
 
     #[test]
-    fn test_error_handling() {
+    fn test_truthiness() {
         let tests = vec![
+            ("if (0) { 1 } else { 2 }", Object::Integer(1)),
+            ("if (\"\") { 1 } else { 2 }", Object::Integer(1)),
+            ("if ([]) { 1 } else { 2 }", Object::Integer(1)),
+            ("if ({}) { 1 } else { 2 }", Object::Integer(1)),
+            ("if (if (false) { 1 }) { 1 } else { 2 }", Object::Integer(2)),
+            ("if (false) { 1 } else { 2 }", Object::Integer(2)),
+            (
+                "let i = 0; while (if (i < 3) { i }) { i = i + 1; } i",
+                Object::Integer(3),
+            ),
             (
-                "5 + true;",
-                "mismatch expression operation: Integer(5) + Boolean(true)",
+                "len(filter([0, \"\", [], true, false], fn(x) { x }))",
+                Object::Integer(4),
             ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input), "{}", input);
+        }
+
+        assert!(is_truthy(&Object::Integer(0)));
+        assert!(is_truthy(&Object::String(String::new().into())));
+        assert!(is_truthy(&Object::Array(vec![].into())));
+        assert!(!is_truthy(&NULL));
+        assert!(!is_truthy(&FALSE));
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = vec![
+            ("5 + true;", "line 1: type mismatch: INTEGER + BOOLEAN"),
+            ("5 + true; 5;", "line 1: type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "line 1: unknown operator: -BOOLEAN"),
+            ("~1.5", "line 1: unknown operator: ~FLOAT"),
+            ("1 << 64", "line 1: shift amount out of range: 64"),
+            ("1 >> -1", "line 1: shift amount out of range: -1"),
+            ("1.0 & 1", "line 1: unknown operator: FLOAT & FLOAT"),
             (
-                "5 + true; 5;",
-                "mismatch expression operation: Integer(5) + Boolean(true)",
+                "true | false",
+                "line 1: unknown operator: BOOLEAN | BOOLEAN",
             ),
-            ("-true", "unknown operator: -Boolean(true)"),
+            ("\"a\" * 2", "line 1: type mismatch: STRING * INTEGER"),
+            ("\"a\" - \"b\"", "line 1: unknown operator: STRING - STRING"),
+            ("\"a\" / \"b\"", "line 1: unknown operator: STRING / STRING"),
+            ("[1] + [2]", "line 1: unknown operator: ARRAY + ARRAY"),
             (
                 "true + false;",
-                "mismatch expression operation: Boolean(true) + Boolean(false)",
+                "line 1: unknown operator: BOOLEAN + BOOLEAN",
             ),
             (
                 "5; true + false; 5",
-                "mismatch expression operation: Boolean(true) + Boolean(false)",
+                "line 1: unknown operator: BOOLEAN + BOOLEAN",
             ),
             (
                 "if (10 > 1) { true + false; }",
-                "mismatch expression operation: Boolean(true) + Boolean(false)",
+                "line 1: unknown operator: BOOLEAN + BOOLEAN",
             ),
             (
                 "if (10 > 1) { if (10 > 1) { return true + false; } return 1; }",
-                "mismatch expression operation: Boolean(true) + Boolean(false)",
+                "line 1: unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("foobar", "line 1: identifier not found: foobar"),
+            ("9223372036854775807 + 1", "line 1: integer overflow"),
+            ("-9223372036854775807 - 2", "line 1: integer overflow"),
+            ("9223372036854775807 * 2", "line 1: integer overflow"),
+            ("-(-9223372036854775807 - 1)", "line 1: integer overflow"),
+            (
+                "let min = -9223372036854775807 - 1; -min",
+                "line 1: integer overflow",
+            ),
+            ("1 / 0", "line 1: division by zero"),
+            ("let x = 1; x /= 0", "line 1: division by zero"),
+            ("reduce([1, 0], 1, (/))", "line 1: division by zero"),
+            (
+                "(-9223372036854775807 - 1) / -1",
+                "line 1: integer overflow",
             ),
-            ("foobar", "identifier not found: foobar"),
         ];
 
         for (input, expect) in tests {
@@ -323,6 +1337,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_error_lines() {
+        let tests = vec![
+            (
+                "let a = 1;\nlet b = 2;\nfoo + a;\nb",
+                "line 3: identifier not found: foo",
+            ),
+            (
+                "let f = fn(x) {\n  x + true\n};\n\n/* call */ f(1);",
+                "line 5: type mismatch: INTEGER + BOOLEAN",
+            ),
+            (
+                "let s = \"a\nb\";\nif (true) {\n  1;\n} else {\n  2;\n}\nlen(s) + true",
+                "line 8: type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::Error(expect.to_string()), test_eval(input));
+        }
+    }
+
     #[test]
     fn test_let_statements() {
         let tests = vec![
@@ -338,11 +1374,1552 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_string_expressions() {
+        let tests = vec![
+            (
+                "\"Hello World!\"",
+                Object::String("Hello World!".to_string().into()),
+            ),
+            (
+                "\"Hello\" + \" \" + \"World!\"",
+                Object::String("Hello World!".to_string().into()),
+            ),
+            ("\"a\" == \"a\"", TRUE),
+            ("\"a\" != \"a\"", FALSE),
+            ("\"apple\" < \"banana\"", TRUE),
+            ("\"apple\" > \"banana\"", FALSE),
+            ("\"ab\" < \"abc\"", TRUE),
+            ("\"abc\" <= \"ab\"", FALSE),
+            ("\"b\" >= \"abc\"", TRUE),
+            ("\"a\" <= \"a\"", TRUE),
+            ("\"Z\" < \"a\"", TRUE),
+            ("len(\"four\")", Object::Integer(4)),
+            (
+                "{\"one\": 1}",
+                Object::Hash(
+                    HashPairs::from([(HashKey::String("one".to_string()), Object::Integer(1))])
+                        .into(),
+                ),
+            ),
+            (
+                "\"a\" - \"b\"",
+                Object::Error("line 1: unknown operator: STRING - STRING".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_chars_builtin() {
+        let tests = vec![
+            ("chars(\"héllo\")", "[\"h\", \"é\", \"l\", \"l\", \"o\"]"),
+            ("chars(\"🐒!\")", "[\"🐒\", \"!\"]"),
+            ("chars(\"\")", "[]"),
+            ("join(reverse(chars(\"abc\")), \"-\")", "\"c-b-a\""),
+            (
+                "chars(1)",
+                "ERROR: line 1: argument to `chars` must be a string, got INTEGER",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_string_builtins() {
+        let tests = vec![
+            ("trim(\"  padded \t\")", "padded"),
+            ("trim(\"\")", ""),
+            ("lower(\"MiXeD\")", "mixed"),
+            ("upper(\"MiXeD\")", "MIXED"),
+            ("lower(\"ÀÉÎ\")", "àéî"),
+            ("replace(\"banana\", \"an\", \"AN\")", "bANANa"),
+            ("replace(\"banana\", \"a\", \"\")", "bnn"),
+            ("replace(\"banana\", \"na\", \"n\")", "bann"),
+            ("replace(\"aaa\", \"aa\", \"bb\")", "bba"),
+            ("let s = \" Keep \"; trim(s) + s", "Keep Keep "),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::String(expect.to_string().into()), test_eval(input));
+        }
+
+        assert_eq!(
+            Object::Error("line 1: argument to `trim` must be a string, got INTEGER".to_string()),
+            test_eval("trim(1)")
+        );
+        assert_eq!(
+            Object::Error("line 1: wrong number of arguments: expected 3, got 2".to_string()),
+            test_eval("replace(\"a\", \"b\")")
+        );
+        assert_eq!(
+            Object::Error(
+                "line 1: arguments to `replace` must be strings, got STRING, INTEGER, STRING"
+                    .to_string()
+            ),
+            test_eval("replace(\"a\", 1, \"b\")")
+        );
+    }
+
+    #[test]
+    fn test_type_builtin() {
+        let tests = vec![
+            ("type(5)", "INTEGER"),
+            ("type(2.5)", "FLOAT"),
+            ("type(true)", "BOOLEAN"),
+            ("type(\"s\")", "STRING"),
+            ("type([1, 2])", "ARRAY"),
+            ("type({1: 2})", "HASH"),
+            ("type(if (false) { 1 })", "NULL"),
+            ("type(fn(x) { x })", "FUNCTION"),
+            ("type(len)", "BUILTIN"),
+            ("type((+))", "BUILTIN"),
+            ("type(type(1))", "STRING"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::String(expect.to_string().into()), test_eval(input));
+        }
+
+        assert_eq!(
+            Object::Error("line 1: wrong number of arguments: expected 1, got 0".to_string()),
+            test_eval("type()")
+        );
+        assert_eq!(
+            Object::Error("line 1: wrong number of arguments: expected 1, got 2".to_string()),
+            test_eval("type(1, 2)")
+        );
+    }
+
+    #[test]
+    fn test_str_builtin() {
+        let tests = vec![
+            ("str(42)", "42"),
+            ("str(-7)", "-7"),
+            ("str(true)", "true"),
+            ("str(\"hi\")", "hi"),
+            ("str(1.5)", "1.5"),
+            ("str([1, \"a\"])", "[1, \"a\"]"),
+            ("\"count: \" + str(1 + 2)", "count: 3"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::String(expect.to_string().into()), test_eval(input));
+        }
+
+        assert_eq!(
+            Object::Error("line 1: wrong number of arguments: expected 1, got 2".to_string()),
+            test_eval("str(1, 2)")
+        );
+    }
+
+    #[test]
+    fn test_int_builtin() {
+        let tests = vec![
+            ("int(\"123\")", Object::Integer(123)),
+            ("int(\" -42\\n\")", Object::Integer(-42)),
+            ("int(7)", Object::Integer(7)),
+            ("int(str(99)) + 1", Object::Integer(100)),
+            (
+                "int(\"abc\")",
+                Object::Error("line 1: cannot parse \"abc\" as an integer".to_string()),
+            ),
+            (
+                "int(\"99999999999999999999\")",
+                Object::Error(
+                    "line 1: cannot parse \"99999999999999999999\" as an integer".to_string(),
+                ),
+            ),
+            ("int(true)", Object::Integer(1)),
+            ("int(false)", Object::Integer(0)),
+            (
+                "int(null)",
+                Object::Error("line 1: argument to `int` not supported, got NULL".to_string()),
+            ),
+            (
+                "int()",
+                Object::Error("line 1: wrong number of arguments: expected 1, got 0".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_bool_builtin() {
+        let tests = vec![
+            ("bool(0)", "true"),
+            ("bool(\"\")", "true"),
+            ("bool([])", "true"),
+            ("bool(false)", "false"),
+            ("bool(null)", "false"),
+            ("bool(1 > 2)", "false"),
+            ("int(bool(5))", "1"),
+            (
+                "bool()",
+                "ERROR: line 1: wrong number of arguments: expected 1, got 0",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_slice_builtin() {
+        let tests = vec![
+            ("slice([1, 2, 3, 4], 1, 3)", "[2, 3]"),
+            ("slice([1, 2, 3, 4], 2)", "[3, 4]"),
+            ("slice([1, 2, 3, 4], 0, 10)", "[1, 2, 3, 4]"),
+            ("slice([1, 2, 3, 4], 5)", "[]"),
+            ("slice([1, 2, 3, 4], 3, 1)", "[]"),
+            ("slice(\"hello\", 0, 2)", "\"he\""),
+            ("slice(\"hello\", 3)", "\"lo\""),
+            ("slice(\"hello\", 1, 99)", "\"ello\""),
+            ("slice(\"héllo\", 1, 3)", "\"él\""),
+            (
+                "slice([1], -1)",
+                "ERROR: line 1: slice bounds must not be negative, got -1",
+            ),
+            (
+                "slice(\"a\", 0, \"1\")",
+                "ERROR: line 1: slice bounds must be integers, got STRING",
+            ),
+            (
+                "slice(1, 0)",
+                "ERROR: line 1: argument to `slice` must be an array or a string, got INTEGER",
+            ),
+            (
+                "slice([1])",
+                "ERROR: line 1: wrong number of arguments: expected 2 or 3, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_copy_builtin() {
+        let tests = vec![
+            ("copy([1, [2, 3]])", "[1, [2, 3]]"),
+            ("copy({\"a\": [1]})", "{\"a\": [1]}"),
+            ("copy(\"s\")", "\"s\""),
+            ("copy(5)", "5"),
+            (
+                "let a = [1, [2]]; let b = copy(a); b = b.slice(1); [a, b]",
+                "[[1, [2]], [[2]]]",
+            ),
+            (
+                "let h = {\"a\": 1}; let c = delete(copy(h), \"a\"); [h, c]",
+                "[{\"a\": 1}, {}]",
+            ),
+            (
+                "copy()",
+                "ERROR: line 1: wrong number of arguments: expected 1, got 0",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_hash_builtins() {
+        let tests = vec![
+            (
+                "keys({\"b\": 2, \"a\": 1, \"c\": 3})",
+                "[\"b\", \"a\", \"c\"]",
+            ),
+            ("values({\"b\": 2, \"a\": 1, \"c\": 3})", "[2, 1, 3]"),
+            ("keys({})", "[]"),
+            (
+                "keys({2: 1, true: 1, \"x\": 1, 1: 1})",
+                "[2, true, \"x\", 1]",
+            ),
+            (
+                "let h = {\"a\": 1, \"b\": 2}; let g = delete(h, \"a\"); [keys(g), keys(h)]",
+                "[[\"b\"], [\"a\", \"b\"]]",
+            ),
+            ("delete({1: 1}, 2)", "{1: 1}"),
+            (
+                "keys([1])",
+                "ERROR: line 1: argument to `keys` must be a hash, got ARRAY",
+            ),
+            (
+                "values({}, {})",
+                "ERROR: line 1: wrong number of arguments: expected 1, got 2",
+            ),
+            (
+                "delete([1], 0)",
+                "ERROR: line 1: first argument to `delete` must be a hash, got ARRAY",
+            ),
+            (
+                "delete({}, [])",
+                "ERROR: line 1: unusable as hash key: ARRAY",
+            ),
+            (
+                "delete({})",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_contains_builtin() {
+        let tests = vec![
+            ("contains([1, 2, 3], 2)", "true"),
+            ("contains([1, 2, 3], 4)", "false"),
+            ("contains([[1], \"a\"], [1])", "true"),
+            ("contains([], 1)", "false"),
+            ("contains(\"hello\", \"ell\")", "true"),
+            ("contains(\"hello\", \"\")", "true"),
+            ("contains(\"hello\", \"Hell\")", "false"),
+            ("contains({\"a\": 1}, \"a\")", "true"),
+            ("contains({\"a\": 1}, 1)", "false"),
+            ("contains([1, 2].map(fn(x) { x * 2 }), 4)", "true"),
+            (
+                "contains(\"hello\", 1)",
+                "ERROR: line 1: can only search a string for a string, got INTEGER",
+            ),
+            (
+                "contains({}, [])",
+                "ERROR: line 1: unusable as hash key: ARRAY",
+            ),
+            (
+                "contains(1, 1)",
+                "ERROR: line 1: first argument to `contains` must be an array, a string or a hash, got INTEGER",
+            ),
+            (
+                "contains([1])",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_numeric_builtins() {
+        let tests = vec![
+            ("abs(-5)", "5"),
+            ("abs(5)", "5"),
+            ("abs(-2.5)", "2.5"),
+            ("min(3, 7)", "3"),
+            ("max(3, 7)", "7"),
+            ("min(7, -3)", "-3"),
+            ("max(2, 2.5)", "2.5"),
+            ("min(2, 2.0)", "2"),
+            (
+                "max(9223372036854775806, 9223372036854775807)",
+                "9223372036854775807",
+            ),
+            (
+                "abs(-9223372036854775807 - 1)",
+                "ERROR: line 1: integer overflow",
+            ),
+            (
+                "abs(\"5\")",
+                "ERROR: line 1: argument to `abs` must be a number, got STRING",
+            ),
+            (
+                "min(1, true)",
+                "ERROR: line 1: arguments to `min` must be numbers, got INTEGER and BOOLEAN",
+            ),
+            (
+                "max(1)",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_range_builtin() {
+        let tests = vec![
+            ("range(3)", "[0, 1, 2]"),
+            ("range(2, 5)", "[2, 3, 4]"),
+            ("range(0, 10, 3)", "[0, 3, 6, 9]"),
+            ("range(5, 0, -2)", "[5, 3, 1]"),
+            ("range(0)", "[]"),
+            ("range(-2)", "[]"),
+            ("range(5, 2)", "[]"),
+            (
+                "range(9223372036854775806, 9223372036854775807, 5)",
+                "[9223372036854775806]",
+            ),
+            (
+                "let sum = 0; for (i in range(1, 5)) { sum += i; } sum",
+                "10",
+            ),
+            (
+                "range(0, 5, 0)",
+                "ERROR: line 1: range step must not be zero",
+            ),
+            (
+                "range(\"3\")",
+                "ERROR: line 1: arguments to `range` must be integers, got STRING",
+            ),
+            (
+                "range()",
+                "ERROR: line 1: wrong number of arguments: expected 1 to 3, got 0",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reverse_builtin() {
+        let tests = vec![
+            ("reverse([1, 2, 3])", "[3, 2, 1]"),
+            ("reverse(\"abc\")", "\"cba\""),
+            ("reverse([])", "[]"),
+            ("reverse(\"\")", "\"\""),
+            ("reverse(\"añb🐒\")", "\"🐒bña\""),
+            ("let a = [1, 2]; reverse(a); a", "[1, 2]"),
+            (
+                "reverse(1)",
+                "ERROR: line 1: argument to `reverse` must be an array or a string, got INTEGER",
+            ),
+            (
+                "reverse([1], [2])",
+                "ERROR: line 1: wrong number of arguments: expected 1, got 2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_sort_builtin() {
+        let tests = vec![
+            ("sort([3, 1, 2])", "[1, 2, 3]"),
+            ("sort([\"b\", \"a\"])", "[\"a\", \"b\"]"),
+            ("sort([])", "[]"),
+            ("sort([-1, 5, -10, 0, 5])", "[-10, -1, 0, 5, 5]"),
+            ("let a = [2, 1]; [sort(a), a]", "[[1, 2], [2, 1]]"),
+            ("sort([3, 1, 2], fn(a, b) { b - a })", "[3, 2, 1]"),
+            (
+                "sort([[2, \"b\"], [1, \"x\"], [2, \"a\"]], fn(a, b) { a[0] - b[0] })",
+                "[[1, \"x\"], [2, \"b\"], [2, \"a\"]]",
+            ),
+            (
+                "sort([1, \"a\"])",
+                "ERROR: line 1: cannot sort an array mixing INTEGER and STRING",
+            ),
+            (
+                "sort([1, [2]])",
+                "ERROR: line 1: can only sort integers or strings without a comparator, got ARRAY",
+            ),
+            (
+                "sort([1, 2], fn(a, b) { true })",
+                "ERROR: line 1: comparator passed to `sort` must return an integer, got BOOLEAN",
+            ),
+            (
+                "sort([1, 2], fn(a, b) { a + \"x\" })",
+                "ERROR: line 1: type mismatch: INTEGER + STRING",
+            ),
+            (
+                "sort(\"ba\")",
+                "ERROR: line 1: first argument to `sort` must be an array, got STRING",
+            ),
+            (
+                "sort([1], 1)",
+                "ERROR: line 1: second argument to `sort` must be a function, got INTEGER",
+            ),
+            (
+                "sort()",
+                "ERROR: line 1: wrong number of arguments: expected 1 or 2, got 0",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+
+        // An inconsistent comparator still yields the elements in some order.
+        let sorted = test_eval("sort([1, 2, 3], fn(a, b) { 1 })");
+        assert!(
+            matches!(&sorted, Object::Array(elements) if elements.len() == 3),
+            "{}",
+            sorted.inspect()
+        );
+    }
+
+    #[test]
+    fn test_split_builtin() {
+        let tests = vec![
+            ("split(\"a,b,c\", \",\")", "[\"a\", \"b\", \"c\"]"),
+            ("split(\"abc\", \"\")", "[\"a\", \"b\", \"c\"]"),
+            ("split(\"a, b\", \", \")", "[\"a\", \"b\"]"),
+            ("split(\",a,\", \",\")", "[\"\", \"a\", \"\"]"),
+            ("split(\"abc\", \"x\")", "[\"abc\"]"),
+            ("split(\"\", \"\")", "[]"),
+            ("split(\"ñü\", \"\")", "[\"ñ\", \"ü\"]"),
+            (
+                "split(1, \",\")",
+                "ERROR: line 1: arguments to `split` must be strings, got INTEGER and STRING",
+            ),
+            (
+                "split(\"a\")",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_join_builtin() {
+        let tests = vec![
+            ("join([\"a\", \"b\", \"c\"], \"-\")", "\"a-b-c\""),
+            ("join([\"a\"], \", \")", "\"a\""),
+            ("join([], \",\")", "\"\""),
+            ("join(split(\"a,b\", \",\"), \"\")", "\"ab\""),
+            (
+                "join([1, 2], \",\")",
+                "ERROR: line 1: elements passed to `join` must be strings, got INTEGER",
+            ),
+            (
+                "join(\"ab\", \",\")",
+                "ERROR: line 1: arguments to `join` must be an array and a string, got STRING and STRING",
+            ),
+            (
+                "join([\"a\"])",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_method_calls() {
+        let tests = vec![
+            ("[1, 2, 3].len()", "3"),
+            ("\"hi\".len()", "2"),
+            ("\" Hi \".trim().upper()", "\"HI\""),
+            ("\"a,b\".split(\",\").join(\"-\")", "\"a-b\""),
+            ("let a = [1, 2, 3]; a.slice(1).len() + 1", "3"),
+            ("[1, 2].map(fn(x) { x * 2 })", "[2, 4]"),
+            ("[1, 2].foo()", "ERROR: line 1: unknown method: ARRAY.foo"),
+            (
+                "5.len()",
+                "ERROR: line 1: argument to `len` not supported, got INTEGER",
+            ),
+            (
+                "\"a\".len(1)",
+                "ERROR: line 1: wrong number of arguments: expected 1, got 2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_puts_builtin() {
+        let mut output = vec![];
+        let mut ctx = EvalContext::new();
+        ctx.set_output(&mut output);
+
+        let program = Parser::new(Lexer::new(r#"puts("a\tb", 1, ["c"])"#)).parse_program();
+        let env = Environment::new().into();
+
+        assert_eq!(Some(NULL), eval_program_with(program, &env, &mut ctx));
+        drop(ctx);
+        assert_eq!("a\tb\n1\n[\"c\"]\n", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_print_builtin() {
+        let mut output = vec![];
+        let mut ctx = EvalContext::new();
+        ctx.set_output(&mut output);
+
+        let input = r#"let y = print(1 + 2); print("s"); [y, print([y])]"#;
+        let program = Parser::new(Lexer::new(input)).parse_program();
+        let env = Environment::new().into();
+
+        assert_eq!(
+            Some(Object::Array(
+                vec![
+                    Object::Integer(3),
+                    Object::Array(vec![Object::Integer(3)].into())
+                ]
+                .into()
+            )),
+            eval_program_with(program, &env, &mut ctx)
+        );
+        drop(ctx);
+        assert_eq!("3\"s\"[3]", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_chained_string_builtins_match_naive() {
+        let inputs = vec![
+            "  Hello World  ",
+            "AAA bbb aaa",
+            "no change",
+            "",
+            "  Ünïcödé Åa  ",
+            "aaaa",
+        ];
+
+        for input in inputs {
+            let expect = input.replace('a', "b").trim().to_lowercase();
+            let program = format!("lower(trim(replace(\"{}\", \"a\", \"b\")))", input);
+            assert_eq!(
+                Object::String(expect.into()),
+                test_eval(&program),
+                "{}",
+                input
+            );
+
+            let expect = input.replace("a", "xyz").to_uppercase().trim().to_string();
+            let program = format!("trim(upper(replace(\"{}\", \"a\", \"xyz\")))", input);
+            assert_eq!(
+                Object::String(expect.into()),
+                test_eval(&program),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_builtins_reuse_unshared_strings() {
+        let call = |name, s| {
+            let builtin = builtins::lookup(name).unwrap();
+            match apply_function(&builtin, vec![Object::String(s)], &mut EvalContext::new()) {
+                Object::String(s) => s,
+                other => panic!("unexpected eval object {}", other),
+            }
+        };
+
+        let s = Rc::new("  MiXeD  ".to_string());
+        let ptr = Rc::as_ptr(&s);
+        let s = call("lower", call("trim", s));
+        assert_eq!("mixed", *s);
+        assert_eq!(ptr, Rc::as_ptr(&s));
+
+        let shared = Rc::new("  MiXeD  ".to_string());
+        let s = call("trim", Rc::clone(&shared));
+        assert_eq!("MiXeD", *s);
+        assert_eq!("  MiXeD  ", *shared);
+    }
+
+    #[test]
+    fn test_array_literals() {
+        let tests = vec![
+            ("[]", Object::Array(vec![].into())),
+            (
+                "[1, 2 * 2, 3 + 3]",
+                Object::Array(
+                    vec![Object::Integer(1), Object::Integer(4), Object::Integer(6)].into(),
+                ),
+            ),
+            (
+                "[[true]]",
+                Object::Array(vec![Object::Array(vec![TRUE].into())].into()),
+            ),
+            (
+                "[1, foo]",
+                Object::Error("line 1: identifier not found: foo".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_identifier_reads_share_arrays() {
+        let env = Environment::new().into();
+        let eval = |input| match eval_program(Parser::new(Lexer::new(input)).parse_program(), &env)
+        {
+            Some(Object::Array(elements)) => elements,
+            other => panic!("unexpected eval object {:?}", other),
+        };
+
+        let first = eval("let a = range(10000); a");
+        let second = eval("a");
+        assert!(Rc::ptr_eq(&first, &second));
+
+        let changed = eval("a[0] = 1; a");
+        assert!(!Rc::ptr_eq(&first, &changed));
+        assert_eq!(Object::Integer(0), first[0]);
+        assert_eq!(Object::Integer(1), changed[0]);
+    }
+
+    #[test]
+    fn test_index_expressions() {
+        let tests = vec![
+            ("[1, 2, 3][0]", Object::Integer(1)),
+            ("[1, 2, 3][1 + 1]", Object::Integer(3)),
+            ("let a = [1, 2, 3]; a[0] + a[1] + a[2]", Object::Integer(6)),
+            ("[1, 2, 3][3]", NULL),
+            ("[10, 20, 30][-1]", Object::Integer(30)),
+            ("[10, 20, 30][-3]", Object::Integer(10)),
+            ("[10, 20][-3]", NULL),
+            ("[][-1]", NULL),
+            ("[[1, 2]][0][-1]", Object::Integer(2)),
+            ("{\"a\": 5}[\"a\"]", Object::Integer(5)),
+            ("{\"a\": 5}[\"b\"]", NULL),
+            ("{true: 1}[1 < 2]", Object::Integer(1)),
+            (
+                "{1: 1}[[]]",
+                Object::Error("line 1: unusable as hash key: ARRAY".to_string()),
+            ),
+            (
+                "1[0]",
+                Object::Error("line 1: index operator not supported: INTEGER[INTEGER]".to_string()),
+            ),
+            (
+                "[1][\"a\"]",
+                Object::Error("line 1: index operator not supported: ARRAY[STRING]".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let eval = test_eval("let two = 2; {1: 10 - 9, two: 1 + 1, 1 < 2: 3}");
+
+        let expect = HashPairs::from([
+            (HashKey::Integer(1), Object::Integer(1)),
+            (HashKey::Integer(2), Object::Integer(2)),
+            (HashKey::Boolean(true), Object::Integer(3)),
+        ]);
+        assert_eq!(Object::Hash(expect.into()), eval);
+
+        let tests = vec![
+            ("{\"a\": 1, \"b\": 2}", "{\"a\": 1, \"b\": 2}"),
+            ("{\"b\": 2, \"a\": 1}", "{\"b\": 2, \"a\": 1}"),
+            (
+                "let h = {\"x\": 1, 2: 2}; h[true] = 3; h[\"x\"] = 4; h",
+                "{\"x\": 4, 2: 2, true: 3}",
+            ),
+            ("delete({1: 1, 2: 2, 3: 3}, 2)", "{1: 1, 3: 3}"),
+            ("{1: 1, 2: 2} == {2: 2, 1: 1}", "true"),
+            ("let f = fn() { {\"a\": 1} }; f()", "{\"a\": 1}"),
+            ("if (true) { {} } else { 1 }", "{}"),
+            ("do { {} }", "{}"),
+            ("match (1) { 1 => { {1: 2} }, _ => { {} } }", "{1: 2}"),
+            ("{ let x = 1; {x: 2} }", "{1: 2}"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input).inspect(), "{}", input);
+        }
+
+        assert_eq!(
+            Object::Error("line 1: unusable as hash key: ARRAY".to_string()),
+            test_eval("{[]: 1}")
+        );
+    }
+
+    #[test]
+    fn test_assert_builtin() {
+        let tests = vec![
+            ("assert(true)", NULL),
+            ("assert(1 < 2)", NULL),
+            ("assert(0)", NULL),
+            (
+                "assert(false)",
+                Object::Error("line 1: assertion failed".to_string()),
+            ),
+            (
+                "let x = 5;\nassert(x == 5);\nassert(x > 10);",
+                Object::Error("line 3: assertion failed".to_string()),
+            ),
+            (
+                "assert()",
+                Object::Error("line 1: wrong number of arguments: expected 1, got 0".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_assert_eq_builtin() {
+        let tests = vec![
+            ("assert_eq(1, 1)", NULL),
+            ("assert_eq([1, [2]], [1, [2]])", NULL),
+            (
+                "assert_eq(1, 2)",
+                Object::Error("line 1: assertion failed: 1 != 2".to_string()),
+            ),
+            (
+                "assert_eq([1, 2, 3, 4], [1, 2, 5, 4])",
+                Object::Error(
+                    "line 1: assertion failed: [1, 2, 3, 4] != [1, 2, 5, 4] (first difference at index 2: 3 != 5)"
+                        .to_string(),
+                ),
+            ),
+            (
+                "assert_eq([1, [2, 3]], [1, [2]])",
+                Object::Error(
+                    "line 1: assertion failed: [1, [2, 3]] != [1, [2]] (first difference at index 1: [2, 3] != [2])"
+                        .to_string(),
+                ),
+            ),
+            (
+                "assert_eq([1, 2], [1, 2, 3])",
+                Object::Error(
+                    "line 1: assertion failed: [1, 2] != [1, 2, 3] (lengths differ: 2 != 3)".to_string(),
+                ),
+            ),
+            (
+                "assert_eq({1: 1}, {1: 2})",
+                Object::Error(
+                    "line 1: assertion failed: {1: 1} != {1: 2} (first difference at key 1: 1 != 2)"
+                        .to_string(),
+                ),
+            ),
+            (
+                "assert_eq({1: 1}, {})",
+                Object::Error("line 1: assertion failed: {1: 1} != {} (key 1 missing on the right)".to_string()),
+            ),
+            (
+                "assert_eq(1)",
+                Object::Error("line 1: wrong number of arguments: expected 2, got 1".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_function_application() {
+        let tests = vec![
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            (
+                "let f = fn(x) { if (x > 1) { if (x > 2) { return 3; } return 2; } 1 }; f(5) + f(2) + f(1)",
+                6,
+            ),
+            ("let f = fn() { let a = 2; let b = 3; a * b }; f()", 6),
+            (
+                "let adder = fn(x) { fn(y) { x + y } }; let addTwo = adder(2); addTwo(3)",
+                5,
+            ),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10)",
+                55,
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(Object::Integer(expect), test_eval(input));
+        }
+
+        assert_eq!(
+            Object::Error("line 1: not a function: INTEGER".to_string()),
+            test_eval("1(2)")
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        let counter =
+            "let newCounter = fn() { let c = 0; fn() { c = c + 1; c } }; let next = newCounter();";
+        let tests = vec![
+            (format!("{} [next(), next(), next()]", counter), "[1, 2, 3]"),
+            (
+                format!("{} let other = newCounter(); next(); next(); [next(), other()]", counter),
+                "[3, 1]",
+            ),
+            // A closure shares its defining scope rather than a snapshot of it.
+            ("let x = 1; let get = fn() { x }; x = 5; get()".to_string(), "5"),
+            ("let x = 1; let set = fn() { x = 7; }; set(); x".to_string(), "7"),
+            (
+                "let pair = fn() { let n = 0; [fn() { n += 1; n }, fn() { n }] }; let p = pair(); p[0](); p[0](); p[1]()"
+                    .to_string(),
+                "2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(&input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_function_arity() {
+        let tests = vec![
+            (
+                "let add = fn(x, y) { x + y }; add(1)",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+            (
+                "let add = fn(x, y) { x + y }; add(1, 2, 3)",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 3",
+            ),
+            (
+                "fn() { 1 }(1)",
+                "ERROR: line 1: wrong number of arguments: expected 0, got 1",
+            ),
+            (
+                "fn f(a, b, ...rest) { a } f(1)",
+                "ERROR: line 1: wrong number of arguments: expected at least 2, got 1",
+            ),
+            (
+                "map([1], fn(x, y) { x })",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+            ("let add = fn(x, y) { x + y }; add(1, 2)", "3"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_rest_parameters() {
+        let tests = vec![
+            (
+                "fn f(first, ...rest) { [first, rest] } f(1, 2, 3)",
+                "[1, [2, 3]]",
+            ),
+            ("fn f(first, ...rest) { rest } f(1)", "[]"),
+            ("fn f(...all) { len(all) } f()", "0"),
+            ("fn sum(...xs) { reduce(xs, 0, (+)) } sum(1, 2, 3, 4)", "10"),
+            ("fn(a, ...rest) { a }", "fn(a, ...rest) { a }"),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_named_functions() {
+        let tests = vec![
+            ("fn add(x, y) { x + y } add(2, 3)", 5),
+            ("fn five() { 5 }; five()", 5),
+            (
+                "fn fact(n) { if (n < 2) { return 1; } n * fact(n - 1) } fact(5)",
+                120,
+            ),
+            ("let f = fn(x) { x * 2 }; f(4)", 8),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(Object::Integer(expected), test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_operator_functions() {
+        let tests = vec![
+            ("(+)(1, 2)", Object::Integer(3)),
+            ("(*)(3, 4)", Object::Integer(12)),
+            ("(-)(10, 4)", Object::Integer(6)),
+            ("(<)(1, 2)", TRUE),
+            ("(<=)(2, 2)", TRUE),
+            ("(>=)(\"b\", \"a\")", TRUE),
+            ("(>=)(1, 2.5)", FALSE),
+            ("let plus = (+); plus(1.5, 1)", Object::Float(2.5)),
+            ("reduce([1, 2, 3], 0, (+))", Object::Integer(6)),
+            ("reduce([1, 2, 3, 4], 1, (*))", Object::Integer(24)),
+            ("reduce([], 7, (+))", Object::Integer(7)),
+            (
+                "reduce([1, 2, 3], 0, fn(acc, x) { acc + x * x })",
+                Object::Integer(14),
+            ),
+            ("let size = len; size([1, 2, 3])", Object::Integer(3)),
+            (
+                "(+)(1)",
+                Object::Error("line 1: wrong number of arguments: expected 2, got 1".to_string()),
+            ),
+            (
+                "reduce([1, true], 0, (+))",
+                Object::Error("line 1: type mismatch: INTEGER + BOOLEAN".to_string()),
+            ),
+            (
+                "reduce(1, 0, (+))",
+                Object::Error(
+                    "line 1: first argument to `reduce` must be an array, got INTEGER".to_string(),
+                ),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_map_builtin() {
+        let tests = vec![
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([], fn(x) { x })", "[]"),
+            ("map([\"a\", \"bc\"], len)", "[1, 2]"),
+            (
+                "let add = fn(n) { fn(x) { x + n } }; map([1, 2], add(10))",
+                "[11, 12]",
+            ),
+            (
+                "map([1], fn(x) { x + true })",
+                "ERROR: line 1: type mismatch: INTEGER + BOOLEAN",
+            ),
+            (
+                "map(1, fn(x) { x })",
+                "ERROR: line 1: first argument to `map` must be an array, got INTEGER",
+            ),
+            (
+                "map([1], 2)",
+                "ERROR: line 1: second argument to `map` must be a function, got INTEGER",
+            ),
+            (
+                "map([1])",
+                "ERROR: line 1: wrong number of arguments: expected 2, got 1",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect());
+        }
+    }
+
+    #[test]
+    fn test_filter_builtin() {
+        let tests = vec![
+            ("filter([1, 2, 3, 4], fn(x) { x > 2 })", "[3, 4]"),
+            ("filter([1, 2, 3], fn(x) { false })", "[]"),
+            ("filter([1, 2, 3], fn(x) { if (x != 2) { x } })", "[1, 3]"),
+            (
+                "filter([\"a\", \"\", \"bc\"], fn(s) { len(s) > 0 })",
+                "[\"a\", \"bc\"]",
+            ),
+            (
+                "filter([1], fn(x) { x + true })",
+                "ERROR: line 1: type mismatch: INTEGER + BOOLEAN",
+            ),
+            (
+                "filter({}, fn(x) { x })",
+                "ERROR: line 1: first argument to `filter` must be an array, got HASH",
+            ),
+            (
+                "filter([1], [2])",
+                "ERROR: line 1: second argument to `filter` must be a function, got ARRAY",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect());
+        }
+    }
+
+    #[test]
+    fn test_reduce_builtin() {
+        let tests = vec![
+            ("reduce([1, 2, 3, 4], 0, fn(acc, x) { acc + x })", "10"),
+            (
+                "reduce([\"a\", \"b\", \"c\"], \"\", fn(acc, s) { acc + s })",
+                "\"abc\"",
+            ),
+            (
+                "reduce([\"x\", \"y\"], \">\", fn(acc, s) { s + acc })",
+                "\"yx>\"",
+            ),
+            ("reduce([], \"\", fn(acc, s) { acc + s })", "\"\""),
+            (
+                "reduce([1], 0, true)",
+                "ERROR: line 1: third argument to `reduce` must be a function, got BOOLEAN",
+            ),
+            (
+                "reduce([1], 0)",
+                "ERROR: line 1: wrong number of arguments: expected 3, got 2",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect());
+        }
+    }
+
+    #[test]
+    fn test_recursion_depth_limit() {
+        assert_eq!(
+            Object::Integer(3628800),
+            test_eval("let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(10)")
+        );
+        assert_eq!(
+            Object::Error("line 1: maximum recursion depth exceeded".to_string()),
+            test_eval("let f = fn(n) { f(n + 1) }; f(0)")
+        );
+
+        let mut ctx = EvalContext::new();
+        ctx.set_max_depth(5);
+        let env = Environment::new().into();
+        let input = "let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };\n\
+                     count(4);\n\
+                     count(5)";
+        let program = Parser::new(Lexer::new(input)).parse_program();
+
+        assert_eq!(
+            Some(Object::Error(
+                "line 3: maximum recursion depth exceeded".to_string()
+            )),
+            eval_program_with(program, &env, &mut ctx)
+        );
+    }
+
+    #[test]
+    fn test_while_statements() {
+        let tests = vec![
+            (
+                "let i = 0; let sum = 0; while (i < 5) { let i = i + 1; let sum = sum + i; } sum",
+                Object::Integer(15),
+            ),
+            ("while (false) { 1 }", NULL),
+            (
+                "let f = fn() { let i = 0; while (true) { let i = i + 1; if (i > 3) { return i; } } }; f()",
+                Object::Integer(4),
+            ),
+            (
+                "let i = 0; while (i < 3) { let i = i + 1; }; return i * 10;",
+                Object::Integer(30),
+            ),
+            (
+                "while (1 + true) { 1 }",
+                Object::Error(
+                    "line 1: type mismatch: INTEGER + BOOLEAN".to_string(),
+                ),
+            ),
+            (
+                "let i = 0; while (i < 3) { let i = i + 1; foo }",
+                Object::Error("line 1: identifier not found: foo".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let env = Environment::new().into();
+
+            assert_eq!(
+                expect,
+                eval_program(parser.parse_program(), &env).unwrap_or(NULL)
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_in_statements() {
+        let tests = vec![
+            (
+                "let total = 0; for (x in [1, 2, 3]) { total += x; } total",
+                Object::Integer(6),
+            ),
+            (
+                "let total = 0; for (x in [1, 2, 3]) { let double = x * 2; total += double; } total",
+                Object::Integer(12),
+            ),
+            (
+                "for (x in [1, 2, 3]) { x } x",
+                Object::Error("line 1: identifier not found: x".to_string()),
+            ),
+            (
+                "let x = 10; for (x in [1, 2]) { x } x",
+                Object::Integer(10),
+            ),
+            (
+                "let s = \"\"; for (w in [\"a\", \"b\", \"c\", \"d\"]) { if (w == \"b\") { continue; } if (w == \"d\") { break; } s += w; } s",
+                Object::String("ac".to_string().into()),
+            ),
+            (
+                "let first = fn(xs) { for (x in xs) { return x; } }; first([7, 8])",
+                Object::Integer(7),
+            ),
+            (
+                "for (x in 5) { x }",
+                Object::Error("line 1: for-in expects an array, got INTEGER".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_c_for_statements() {
+        let tests = vec![
+            (
+                "let sum = 0; for (let i = 0; i < 5; i += 1) { sum += i; } sum",
+                "10",
+            ),
+            (
+                "let i = 100; let sum = 0; for (i = 0; i < 5; i = i + 1) { sum = sum + i; } [sum, i]",
+                "[10, 5]",
+            ),
+            (
+                "let sum = 0; for (let i = 0; i < 10; i += 1) { if (i == 5) { break; } if (i == 1) { continue; } sum += i; } sum",
+                "9",
+            ),
+            ("for (let i = 0; false; i += 1) {} i", "ERROR: line 1: identifier not found: i"),
+            (
+                "let f = fn() { for (let i = 0; true; i += 1) { if (i == 3) { return i; } } }; f()",
+                "3",
+            ),
+            (
+                "for (let i = 0; i < 3; j += 1) {}",
+                "ERROR: line 1: identifier not found: j",
+            ),
+            ("for (let i = x; true; i += 1) {}", "ERROR: line 1: identifier not found: x"),
+        ];
+
+        for (input, expected) in tests {
+            let env = Environment::new().into();
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            assert_eq!(
+                expected,
+                eval_program(program, &env).unwrap_or(NULL).inspect(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let tests = vec![
+            (
+                "let i = 0; while (true) { if (i == 5) { break; } i += 1; } i",
+                Object::Integer(5),
+            ),
+            (
+                "let i = 0; let sum = 0; while (i < 10) { i += 1; if (i - i / 2 * 2 == 0) { continue; } sum += i; } sum",
+                Object::Integer(25),
+            ),
+            (
+                "let n = 0; let i = 0; while (i < 3) { i += 1; let j = 0; while (true) { j += 1; if (j > i) { break; } n += 1; } } n",
+                Object::Integer(6),
+            ),
+            (
+                "let f = fn() { let i = 0; while (true) { i += 1; if (i > 2) { return i * 10; } } }; f()",
+                Object::Integer(30),
+            ),
+            ("break;", Object::Error("line 1: break outside loop".to_string())),
+            (
+                "if (true) { continue; }",
+                Object::Error("line 1: continue outside loop".to_string()),
+            ),
+            (
+                "let f = fn() { break; }; while (true) { f(); }",
+                Object::Error("line 1: break outside loop".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_loop_control_in_initializers() {
+        let tests = vec![
+            (
+                "let i = 0; while (i < 3) { i += 1; let x = if (i == 2) { break; }; } i",
+                "2",
+            ),
+            (
+                "let i = 0; let n = 0; while (i < 4) { i += 1; let x = if (i == 2) { continue; }; n += 1; } n",
+                "3",
+            ),
+            (
+                "let f = fn() { while (true) { return if (true) { break; }; } 7 }; f()",
+                "7",
+            ),
+            (
+                "let x = 0; while (true) { x = if (true) { break; }; } x",
+                "0",
+            ),
+            (
+                "let a = [0]; while (true) { a[0] = if (true) { break; }; } a",
+                "[0]",
+            ),
+            (
+                "let a = [0]; while (true) { a[if (true) { break; }] = 1; } a",
+                "[0]",
+            ),
+            (
+                "let i = 0; while (i < 1) { match (if (true) { break; }) { _ => { i = 5; } } } i",
+                "0",
+            ),
+            (
+                "let x = if (true) { break; };",
+                "ERROR: line 1: break outside loop",
+            ),
+            (
+                "return if (true) { continue; };",
+                "ERROR: line 1: continue outside loop",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = vec![
+            ("let x = 1; x = 2; x", Object::Integer(2)),
+            ("let x = 1; x = x + 1", Object::Integer(2)),
+            (
+                "let i = 0; let sum = 0; while (i < 5) { i = i + 1; sum = sum + i; } sum",
+                Object::Integer(15),
+            ),
+            (
+                "let count = 0; let bump = fn() { count = count + 1; }; bump(); bump(); count",
+                Object::Integer(2),
+            ),
+            (
+                "let x = 1; let f = fn() { let x = 10; x = 20; x }; f() + x",
+                Object::Integer(21),
+            ),
+            (
+                "let a = 0; let b = 0; a = b = 5; a + b",
+                Object::Integer(10),
+            ),
+            (
+                "let a = [0]; let x = 0; a[0] = x = 3; a[0] + x",
+                Object::Integer(6),
+            ),
+            ("let x = 1; (x = 4) * 2 + x", Object::Integer(12)),
+            (
+                "y = 5",
+                Object::Error("line 1: assignment to undeclared variable: y".to_string()),
+            ),
+            (
+                "let f = fn() { let y = 1; }; f(); y = 2",
+                Object::Error("line 1: assignment to undeclared variable: y".to_string()),
+            ),
+            (
+                "let x = 1; x = foo; x",
+                Object::Error("line 1: identifier not found: foo".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let env = Environment::new().into();
+
+            assert_eq!(
+                expect,
+                eval_program(parser.parse_program(), &env).unwrap_or(NULL)
+            );
+        }
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let tests = vec![
+            ("let a = [1, 2, 3]; a[1] = 20; a", "[1, 20, 3]"),
+            ("let a = [1, 2, 3]; a[-1] = 30; a", "[1, 2, 30]"),
+            ("let a = [1, 2, 3]; a[0] += 10", "11"),
+            (
+                "let i = 0; let f = fn() { i += 1; 0 }; let a = [1, 2]; a[f()] += 1; [i, a]",
+                "[1, [2, 2]]",
+            ),
+            ("let h = {\"a\": 1}; h[\"a\"] -= 3; h", "{\"a\": -2}"),
+            ("let a = [[1], [2]]; a[1] = 5; a", "[[1], 5]"),
+            (
+                "let h = {\"a\": 1}; h[\"b\"] = 2; h[\"a\"] = 0; [h[\"a\"], h[\"b\"]]",
+                "[0, 2]",
+            ),
+            ("let a = [1]; let b = a; b[0] = 9; [a, b]", "[[1], [9]]"),
+            ("let a = [1]; let b = copy(a); b[0] = 9; a", "[1]"),
+            (
+                "let a = [0, 0]; let f = fn(i) { a[i] = i + 1; }; f(0); f(1); a",
+                "[1, 2]",
+            ),
+            (
+                "let a = [1, 2, 3]; let i = 0; while (i < 3) { a[i] *= 2; i += 1; } a",
+                "[2, 4, 6]",
+            ),
+            (
+                "let a = [1, 2, 3]; a[3] = 4",
+                "ERROR: line 1: index out of range: 3 for an array of length 3",
+            ),
+            (
+                "let a = [1, 2, 3]; a[-4] = 4",
+                "ERROR: line 1: index out of range: -4 for an array of length 3",
+            ),
+            (
+                "let s = \"abc\"; s[0] = \"x\"",
+                "ERROR: line 1: index assignment not supported: STRING[INTEGER]",
+            ),
+            (
+                "let h = {}; h[[]] = 1",
+                "ERROR: line 1: unusable as hash key: ARRAY",
+            ),
+            (
+                "b[0] = 1",
+                "ERROR: line 1: assignment to undeclared variable: b",
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input).inspect(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let compound = "let i = 0; let sum = 0; while (i < 10) { i += 1; sum += i; } sum";
+        let expanded = "let i = 0; let sum = 0; while (i < 10) { i = i + 1; sum = sum + i; } sum";
+        assert_eq!(Object::Integer(55), test_eval(compound));
+        assert_eq!(test_eval(expanded), test_eval(compound));
+
+        let tests = vec![
+            ("let x = 10; x -= 4; x", Object::Integer(6)),
+            ("let x = 10; x *= 3; x", Object::Integer(30)),
+            ("let x = 10; x /= 4; x", Object::Integer(2)),
+            (
+                "let s = \"a\"; s += \"b\"; s",
+                Object::String("ab".to_string().into()),
+            ),
+            (
+                "y += 1",
+                Object::Error("line 1: identifier not found: y".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            assert_eq!(expect, test_eval(input));
+        }
+    }
+
+    #[test]
+    fn test_resolver_hook() {
+        let mut ctx = EvalContext::new();
+        ctx.set_resolver(|name| match name {
+            "answer" => Some(Object::Integer(42)),
+            _ => None,
+        });
+
+        let tests = vec![
+            ("answer + 1", Object::Integer(43)),
+            ("let answer = 1; answer", Object::Integer(1)),
+            ("len([answer])", Object::Integer(1)),
+            (
+                "question",
+                Object::Error("line 1: identifier not found: question".to_string()),
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let env = Environment::new().into();
+
+            assert_eq!(
+                Some(expect),
+                eval_program_with(parser.parse_program(), &env, &mut ctx)
+            );
+        }
+    }
+
+    #[test]
+    fn test_executor_steps() {
+        let lexer = Lexer::new("let a = 5; a * 2; a + 1;");
+        let mut parser = Parser::new(lexer);
+        let mut executor = Executor::new(parser.parse_program(), Environment::new().into());
+
+        assert_eq!(Some(None), executor.step());
+        assert_eq!(
+            Some(&Object::Integer(5)),
+            executor.env().borrow().get("a").as_deref()
+        );
+        assert_eq!(Some(Some(Object::Integer(10))), executor.step());
+        assert!(!executor.is_done());
+        assert_eq!(Some(Some(Object::Integer(6))), executor.step());
+        assert!(executor.is_done());
+        assert_eq!(None, executor.step());
+    }
+
+    #[test]
+    fn test_executor_halts_on_return_and_error() {
+        let tests = vec![
+            (
+                "1; return 2; 3;",
+                vec![Some(Object::Integer(1)), Some(Object::Integer(2))],
+            ),
+            (
+                "1; foo; 3;",
+                vec![
+                    Some(Object::Integer(1)),
+                    Some(Object::Error(
+                        "line 1: identifier not found: foo".to_string(),
+                    )),
+                ],
+            ),
+        ];
+
+        for (input, expect) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let executor = Executor::new(parser.parse_program(), Environment::new().into());
+
+            assert_eq!(expect, executor.collect::<Vec<_>>());
+        }
+    }
+
     fn test_eval(input: &str) -> Object {
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
-        let mut env = Environment::new();
+        let env = Environment::new().into();
 
-        eval_program(parser.parse_program(), &mut env).unwrap()
+        eval_program(parser.parse_program(), &env).unwrap()
     }
 }
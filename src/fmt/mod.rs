@@ -0,0 +1,416 @@
+use crate::ast::{parameter_list, BlockStatement, Expression, Program, Statement};
+use crate::lexer::Lexer;
+use crate::parser::{operator_precedence, ParseError, Parser, Precedence};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Indent {
+    Spaces(usize),
+    Tab,
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Indent::Spaces(2)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FormatOptions {
+    pub indent: Indent,
+}
+
+pub fn format_program(source: &str) -> Result<String, Vec<ParseError>> {
+    format_program_with(source, &FormatOptions::default())
+}
+
+// Comments are not part of the AST, so formatting drops them.
+pub fn format_program_with(
+    source: &str,
+    options: &FormatOptions,
+) -> Result<String, Vec<ParseError>> {
+    let lexer = Lexer::new(source);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        return Err(parser.errors());
+    }
+
+    let formatter = Formatter {
+        indent: match options.indent {
+            Indent::Spaces(n) => " ".repeat(n),
+            Indent::Tab => "\t".to_string(),
+        },
+    };
+
+    Ok(formatter.program(&program))
+}
+
+struct Formatter {
+    indent: String,
+}
+
+impl Formatter {
+    fn program(&self, program: &Program) -> String {
+        let mut out = String::new();
+        for stmt in program.all() {
+            out.push_str(&self.statement(stmt, 0));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn statement(&self, stmt: &Statement, depth: usize) -> String {
+        let body = match stmt {
+            Statement::Let(ident, expr) => {
+                format!("let {} = {};", ident, self.expression(expr, depth))
+            }
+            Statement::Return(expr) => format!("return {};", self.expression(expr, depth)),
+            Statement::Expression(
+                expr @ (Expression::If(..) | Expression::Do(_) | Expression::Match(..)),
+            ) => self.expression(expr, depth),
+            Statement::Expression(expr) => format!("{};", self.expression(expr, depth)),
+            Statement::While(cond, body) => format!(
+                "while ({}) {}",
+                self.expression(cond, depth),
+                self.block(body, depth)
+            ),
+            Statement::ForIn(ident, iterable, body) => format!(
+                "for ({} in {}) {}",
+                ident,
+                self.expression(iterable, depth),
+                self.block(body, depth)
+            ),
+            Statement::ForC(init, cond, post, body) => {
+                let init = match &**init {
+                    Statement::Expression(expr) => format!("{};", self.expression(expr, depth)),
+                    stmt => self.statement(stmt, 0),
+                };
+                let post = match &**post {
+                    Statement::Expression(expr) => self.expression(expr, depth),
+                    stmt => self.statement(stmt, 0),
+                };
+
+                format!(
+                    "for ({} {}; {}) {}",
+                    init,
+                    self.expression(cond, depth),
+                    post,
+                    self.block(body, depth)
+                )
+            }
+            Statement::Block(body) => self.block(body, depth),
+            Statement::Break => "break;".to_string(),
+            Statement::Continue => "continue;".to_string(),
+        };
+
+        format!("{}{}", self.indent.repeat(depth), body)
+    }
+
+    fn block(&self, block: &BlockStatement, depth: usize) -> String {
+        if block.is_empty() {
+            return "{}".to_string();
+        }
+
+        let mut out = "{\n".to_string();
+        for stmt in block {
+            out.push_str(&self.statement(stmt, depth + 1));
+            out.push('\n');
+        }
+        out.push_str(&self.indent.repeat(depth));
+        out.push('}');
+
+        out
+    }
+
+    fn expression(&self, expr: &Expression, depth: usize) -> String {
+        match expr {
+            Expression::Prefix(op, right) => {
+                format!("{}{}", op, self.operand(right, Precedence::Prefix, depth))
+            }
+            Expression::Infix(left, op, right) => {
+                let precedence = operator_precedence(op);
+                let left = match **left {
+                    Expression::Infix(_, ref left_op, _)
+                        if operator_precedence(left_op) == precedence =>
+                    {
+                        self.expression(left, depth)
+                    }
+                    _ => self.operand(left, operator_precedence(op), depth),
+                };
+
+                format!("{} {} {}", left, op, self.operand(right, precedence, depth))
+            }
+            Expression::If(cond, conseq, alter) => {
+                let mut out = format!(
+                    "if ({}) {}",
+                    self.expression(cond, depth),
+                    self.block(conseq, depth)
+                );
+                if let Some(alter) = alter {
+                    out.push_str(&format!(" else {}", self.block(alter, depth)));
+                }
+
+                out
+            }
+            Expression::Do(body) => format!("do {}", self.block(body, depth)),
+            Expression::Match(value, arms) if arms.is_empty() => {
+                format!("match ({}) {{}}", self.expression(value, depth))
+            }
+            Expression::Match(value, arms) => {
+                let mut out = format!("match ({}) {{\n", self.expression(value, depth));
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    out.push_str(&format!(
+                        "{}{} => {}",
+                        self.indent.repeat(depth + 1),
+                        pattern,
+                        self.block(body, depth + 1)
+                    ));
+                    out.push_str(if i + 1 < arms.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&self.indent.repeat(depth));
+                out.push('}');
+
+                out
+            }
+            Expression::Ternary(cond, conseq, alter) => format!(
+                "{} ? {} : {}",
+                self.operand(cond, Precedence::Ternary, depth),
+                self.expression(conseq, depth),
+                self.expression(alter, depth)
+            ),
+            Expression::Function(parameters, rest, body) => {
+                format!(
+                    "fn({}) {}",
+                    parameter_list(parameters, rest),
+                    self.block(body, depth)
+                )
+            }
+            Expression::Call(function, arguments) => format!(
+                "{}({})",
+                self.operand(function, Precedence::Call, depth),
+                self.list(arguments.iter().map(|arg| &**arg), depth)
+            ),
+            Expression::MethodCall(receiver, method, arguments) => format!(
+                "{}.{}({})",
+                self.operand(receiver, Precedence::Call, depth),
+                method,
+                self.list(arguments, depth)
+            ),
+            Expression::Array(elements) => format!("[{}]", self.list(elements, depth)),
+            Expression::Index(left, index) => format!(
+                "{}[{}]",
+                self.operand(left, Precedence::Call, depth),
+                self.expression(index, depth)
+            ),
+            Expression::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}: {}",
+                            self.expression(key, depth),
+                            self.expression(value, depth)
+                        )
+                    })
+                    .collect();
+
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Expression::Assign(name, value) => {
+                format!("{} = {}", name, self.expression(value, depth))
+            }
+            Expression::IndexAssign(name, index, value) => format!(
+                "{}[{}] = {}",
+                name,
+                self.expression(index, depth),
+                self.expression(value, depth)
+            ),
+            Expression::CompoundIndexAssign(name, index, op, value) => format!(
+                "{}[{}] {}= {}",
+                name,
+                self.expression(index, depth),
+                op,
+                self.expression(value, depth)
+            ),
+            _ => expr.to_string(),
+        }
+    }
+
+    fn list<'e>(&self, exprs: impl IntoIterator<Item = &'e Expression>, depth: usize) -> String {
+        let exprs: Vec<String> = exprs
+            .into_iter()
+            .map(|e| self.expression(e, depth))
+            .collect();
+        exprs.join(", ")
+    }
+
+    // Formats `expr` as an operand of an operator binding at `outer`, adding
+    // parentheses only where the tree would otherwise parse differently.
+    fn operand(&self, expr: &Expression, outer: Precedence, depth: usize) -> String {
+        let inner = match expr {
+            Expression::Prefix(..) => Precedence::Prefix,
+            Expression::Infix(_, op, _) => operator_precedence(op),
+            Expression::Assign(..)
+            | Expression::IndexAssign(..)
+            | Expression::CompoundIndexAssign(..) => Precedence::Assign,
+            Expression::Ternary(..) => Precedence::Ternary,
+            _ => Precedence::Call,
+        };
+
+        let chains = inner == outer && matches!(outer, Precedence::Prefix | Precedence::Call);
+        if inner > outer || chains {
+            self.expression(expr, depth)
+        } else {
+            format!("({})", self.expression(expr, depth))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTED: &str = "let f = fn(x, y) { if (x > y) { let z = x - y; return z * 2; } else { while (y > x) { let y = y - 1; } y } }; f(1, 2);";
+
+    #[test]
+    fn test_format_with_indent() {
+        let tests = vec![
+            (
+                Indent::Spaces(2),
+                "let f = fn(x, y) {
+  if (x > y) {
+    let z = x - y;
+    return z * 2;
+  } else {
+    while (y > x) {
+      let y = y - 1;
+    }
+    y;
+  }
+};
+f(1, 2);
+",
+            ),
+            (
+                Indent::Spaces(4),
+                "let f = fn(x, y) {
+    if (x > y) {
+        let z = x - y;
+        return z * 2;
+    } else {
+        while (y > x) {
+            let y = y - 1;
+        }
+        y;
+    }
+};
+f(1, 2);
+",
+            ),
+            (
+                Indent::Tab,
+                "let f = fn(x, y) {\n\tif (x > y) {\n\t\tlet z = x - y;\n\t\treturn z * 2;\n\t} else {\n\t\twhile (y > x) {\n\t\t\tlet y = y - 1;\n\t\t}\n\t\ty;\n\t}\n};\nf(1, 2);\n",
+            ),
+        ];
+
+        for (indent, expected) in tests {
+            let options = FormatOptions { indent };
+            assert_eq!(
+                Ok(expected.to_string()),
+                format_program_with(NESTED, &options)
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_program_loops() {
+        let input = "let total=0;for(x in [1,2,3]){if(x==2){continue;}total+=x<<1;while(true){break}}puts(\"sum:\\t\"+str(total))";
+        let expected = "let total = 0;
+for (x in [1, 2, 3]) {
+  if (x == 2) {
+    continue;
+  }
+  total = total + (x << 1);
+  while (true) {
+    break;
+  }
+}
+puts(\"sum:\\t\" + str(total));
+";
+
+        let formatted = format_program(input).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(Ok(formatted.clone()), format_program(&formatted));
+    }
+
+    #[test]
+    fn test_format_match() {
+        let input = "let s=match(n-3){0=>{\"fizz\"},-1=>{},_=>{str(n)}};match(x){}";
+        let expected = "let s = match (n - 3) {
+  0 => {
+    \"fizz\";
+  },
+  -1 => {},
+  _ => {
+    str(n);
+  }
+};
+match (x) {}
+";
+
+        let formatted = format_program(input).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(Ok(formatted.clone()), format_program(&formatted));
+    }
+
+    #[test]
+    fn test_format_parentheses() {
+        let tests = vec![
+            ("1+2*3", "1 + 2 * 3;\n"),
+            ("(1+2)*3", "(1 + 2) * 3;\n"),
+            ("1-(2-3)", "1 - (2 - 3);\n"),
+            ("(1-2)-3", "1 - 2 - 3;\n"),
+            ("-(1+2)", "-(1 + 2);\n"),
+            ("!-a", "!-a;\n"),
+            ("fn(x){x}(1)", "fn(x) {\n  x;\n}(1);\n"),
+            ("[1,2*3,{\"a\":(+)}]", "[1, 2 * 3, {\"a\": (+)}];\n"),
+            ("if(x){}", "if (x) {}\n"),
+            ("x=(x+1)*2", "x = (x + 1) * 2;\n"),
+            ("a[i+1]=-a[i]", "a[i + 1] = -a[i];\n"),
+            ("(-x).abs( )", "(-x).abs();\n"),
+            ("fn(a,...b){b}", "fn(a, ...b) {\n  b;\n};\n"),
+            ("[1,2].len()+1", "[1, 2].len() + 1;\n"),
+            ("x>0?1:-1", "x > 0 ? 1 : -1;\n"),
+            ("(a?b:c)?d:(e?f:g)", "(a ? b : c) ? d : e ? f : g;\n"),
+            ("(a?b:c)*2", "(a ? b : c) * 2;\n"),
+            ("(-a)[0]+-a[ 1 ][2]", "(-a)[0] + -a[1][2];\n"),
+            ("a=b=5", "a = b = 5;\n"),
+            (
+                "for(let i=0;i<3;i+=1){}",
+                "for (let i = 0; i < 3; i = i + 1) {}\n",
+            ),
+            ("(a=1)+f(b=2)", "(a = 1) + f(b = 2);\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(Ok(expected.to_string()), format_program(input));
+        }
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        for indent in [Indent::Spaces(2), Indent::Spaces(4), Indent::Tab] {
+            let options = FormatOptions { indent };
+            let once = format_program_with(NESTED, &options).unwrap();
+            let twice = format_program_with(&once, &options).unwrap();
+
+            assert_eq!(once, twice);
+        }
+    }
+
+    #[test]
+    fn test_format_parse_errors() {
+        assert!(format_program("let = 1;").is_err());
+    }
+}
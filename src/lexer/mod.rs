@@ -1,13 +1,19 @@
 use crate::token::{lookup_ident, Token};
 
+#[derive(Clone)]
 pub struct Lexer<'a> {
     input: &'a str,
-    // current position in input (points to current char)
+    // current byte offset in input (points to current char)
     position: usize,
-    // current reading position in input (after current char)
+    // current byte offset for reading in input (after current char)
     read_position: usize,
     // current char under examination
     ch: char,
+    // 1-based line of the current char
+    line: usize,
+    // byte offset and line where the most recently returned token starts
+    token_start: usize,
+    token_line: usize,
 }
 
 impl<'a> Lexer<'a> {
@@ -17,31 +23,49 @@ impl<'a> Lexer<'a> {
             position: 0,
             read_position: 0,
             ch: '\0',
+            line: 1,
+            token_start: 0,
+            token_line: 1,
         };
         l.read_char();
         l
     }
 
     fn read_char(&mut self) {
+        // Past the end there is only `'\0'`, and offsets must stay within the input
+        // however many times the parser asks for another token.
+        if self.read_position > self.input.len() {
+            return;
+        }
+        if self.ch == '\n' {
+            self.line += 1;
+        }
         self.ch = self.peek_char();
         self.position = self.read_position;
-        self.read_position += 1;
+        self.read_position += self.ch.len_utf8();
     }
 
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        if !self.skip_whitespace() {
+            return Token::Illegal("unterminated block comment".to_string());
+        }
+        self.token_start = self.position;
+        self.token_line = self.line;
 
         let tok = match self.ch {
             '=' => {
                 if self.peek_char() == '=' {
                     self.read_char();
                     Token::Eq
+                } else if self.peek_char() == '>' {
+                    self.read_char();
+                    Token::Arrow
                 } else {
                     Token::Assign
                 }
             }
-            '+' => Token::Plus,
-            '-' => Token::Minus,
+            '+' => self.read_compound(Token::PlusEq, Token::Plus),
+            '-' => self.read_compound(Token::MinusEq, Token::Minus),
             '!' => {
                 if self.peek_char() == '=' {
                     self.read_char();
@@ -50,19 +74,58 @@ impl<'a> Lexer<'a> {
                     Token::Bang
                 }
             }
-            '*' => Token::Asterisk,
-            '/' => Token::Slash,
+            '~' => Token::Tilde,
+            '*' => self.read_compound(Token::AsteriskEq, Token::Asterisk),
+            '/' => self.read_compound(Token::SlashEq, Token::Slash),
 
-            '<' => Token::Lt,
-            '>' => Token::Gt,
+            '&' => Token::BitAnd,
+            '|' => Token::BitOr,
+            '^' => Token::Caret,
+
+            '<' => {
+                if self.peek_char() == '<' {
+                    self.read_char();
+                    Token::Shl
+                } else if self.peek_char() == '=' {
+                    self.read_char();
+                    Token::Le
+                } else {
+                    Token::Lt
+                }
+            }
+            '>' => {
+                if self.peek_char() == '>' {
+                    self.read_char();
+                    Token::Shr
+                } else if self.peek_char() == '=' {
+                    self.read_char();
+                    Token::Ge
+                } else {
+                    Token::Gt
+                }
+            }
 
             ',' => Token::Comma,
             ';' => Token::Semicolon,
+            ':' => Token::Colon,
+            '.' => {
+                if self.input[self.read_position..].starts_with("..") {
+                    self.read_char();
+                    self.read_char();
+                    Token::Ellipsis
+                } else {
+                    Token::Dot
+                }
+            }
+            '?' => Token::Question,
 
             '(' => Token::Lparen,
             ')' => Token::Rparen,
             '{' => Token::Lbrace,
             '}' => Token::Rbrace,
+            '"' => self.read_string(),
+            '[' => Token::Lbracket,
+            ']' => Token::Rbracket,
 
             '\0' => Token::Eof,
             _ => {
@@ -71,7 +134,7 @@ impl<'a> Lexer<'a> {
                 } else if is_digit(self.ch) {
                     return self.read_number();
                 } else {
-                    Token::Illegal
+                    Token::Illegal(format!("illegal character: {}", self.ch))
                 }
             }
         };
@@ -80,12 +143,78 @@ impl<'a> Lexer<'a> {
         tok
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_ascii_whitespace() {
+    pub fn token_offset(&self) -> usize {
+        self.token_start
+    }
+
+    pub fn token_line(&self) -> usize {
+        self.token_line
+    }
+
+    // Converts a byte offset into a 1-based line and column, counting columns in chars.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    // Picks `compound` for an operator directly followed by `=`, as in `+=`.
+    fn read_compound(&mut self, compound: Token, plain: Token) -> Token {
+        if self.peek_char() == '=' {
+            self.read_char();
+            compound
+        } else {
+            plain
+        }
+    }
+
+    // Returns false if an unterminated block comment swallowed the rest of the input.
+    fn skip_whitespace(&mut self) -> bool {
+        loop {
+            while self.ch.is_ascii_whitespace() {
+                self.read_char();
+            }
+
+            if self.ch == '/' && self.peek_char() == '/' {
+                self.skip_line_comment();
+            } else if self.ch == '/' && self.peek_char() == '*' {
+                self.token_start = self.position;
+                self.token_line = self.line;
+                if !self.skip_block_comment() {
+                    return false;
+                }
+            } else {
+                return true;
+            }
+        }
+    }
+
+    fn skip_line_comment(&mut self) {
+        while self.ch != '\n' && self.ch != '\0' {
             self.read_char();
         }
     }
 
+    // Block comments do not nest: the first `*/` closes the comment.
+    fn skip_block_comment(&mut self) -> bool {
+        self.read_char();
+        self.read_char();
+
+        loop {
+            match self.ch {
+                '\0' => return false,
+                '*' if self.peek_char() == '/' => {
+                    self.read_char();
+                    self.read_char();
+                    return true;
+                }
+                _ => self.read_char(),
+            }
+        }
+    }
+
     fn read_identifier(&mut self) -> Token {
         let pos = self.position;
         while is_letter(self.ch) {
@@ -94,25 +223,117 @@ impl<'a> Lexer<'a> {
         lookup_ident(&self.input[pos..self.position])
     }
 
+    // An unknown escape is reported only once the closing quote is found, so the
+    // rest of the literal isn't lexed as code.
+    fn read_string(&mut self) -> Token {
+        let mut s = String::new();
+        let mut unknown_escape = None;
+
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => break,
+                '\0' => return Token::Illegal("unterminated string literal".to_string()),
+                '\\' => {
+                    self.read_char();
+                    match self.ch {
+                        'n' => s.push('\n'),
+                        't' => s.push('\t'),
+                        '"' => s.push('"'),
+                        '\\' => s.push('\\'),
+                        '\0' => return Token::Illegal("unterminated string literal".to_string()),
+                        other => {
+                            unknown_escape.get_or_insert(other);
+                        }
+                    }
+                }
+                ch => s.push(ch),
+            }
+        }
+
+        match unknown_escape {
+            Some(ch) => Token::Illegal(format!("unknown escape sequence: \\{}", ch)),
+            None => Token::String(s),
+        }
+    }
+
+    // Underscores may separate digits, as in `1_000`, but a literal must not
+    // contain a doubled one or one at either end of its integer or fraction.
     fn read_number(&mut self) -> Token {
         let position = self.position;
-        while is_digit(self.ch) {
+        self.read_digits();
+
+        let is_float = self.ch == '.' && is_digit(self.peek_char());
+        if is_float {
+            self.read_char();
+            self.read_digits();
+        }
+
+        let literal = &self.input[position..self.position];
+        let misplaced_separator = literal
+            .split('.')
+            .any(|part| part.ends_with('_') || part.starts_with('_') || part.contains("__"));
+        if misplaced_separator {
+            return Token::Illegal(format!("invalid digit separator in number: {}", literal));
+        }
+
+        let digits = literal.replace('_', "");
+        if is_float {
+            return Token::Float(digits.parse().unwrap());
+        }
+
+        match digits.parse() {
+            Ok(i) => Token::Int(i),
+            Err(_) => Token::Illegal(format!("integer literal too large: {}", literal)),
+        }
+    }
+
+    fn read_digits(&mut self) {
+        while is_digit(self.ch) || self.ch == '_' {
             self.read_char();
         }
-        Token::Int(self.input[position..self.position].parse().unwrap())
     }
 
     fn peek_char(&self) -> char {
-        if self.read_position >= self.input.len() {
-            return '\0';
+        self.input
+            .get(self.read_position..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\0')
+    }
+}
+
+// Lexes the whole input, leaving off the final `Eof`.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = vec![];
+
+    loop {
+        match lexer.next_token() {
+            Token::Eof => return tokens,
+            tok => tokens.push(tok),
         }
+    }
+}
 
-        self.input.chars().nth(self.read_position).unwrap()
+// The inverse of the escapes `read_string` understands, so a string literal prints
+// back as source.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch => out.push(ch),
+        }
     }
+
+    out
 }
 
 fn is_letter(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
+    ch.is_alphabetic() || ch == '_'
 }
 
 fn is_digit(ch: char) -> bool {
@@ -131,7 +352,7 @@ let add = fn(x, y) {
     x + y;
 };
 let result = add(five, ten);
-!-/*5;
+!-/ *5;
 5 < 10 > 5;
 
 if (5 < 10) {
@@ -142,6 +363,10 @@ if (5 < 10) {
 
 10 == 10;
 10 != 9;
+[1, 2];
+{1: 2};
+\"foobar\"
+\"foo bar\"
 ";
 
         let tests = vec![
@@ -218,6 +443,20 @@ if (5 < 10) {
             Token::NotEq,
             Token::Int(9),
             Token::Semicolon,
+            Token::Lbracket,
+            Token::Int(1),
+            Token::Comma,
+            Token::Int(2),
+            Token::Rbracket,
+            Token::Semicolon,
+            Token::Lbrace,
+            Token::Int(1),
+            Token::Colon,
+            Token::Int(2),
+            Token::Rbrace,
+            Token::Semicolon,
+            Token::String("foobar".to_string()),
+            Token::String("foo bar".to_string()),
             Token::Eof,
         ];
 
@@ -228,4 +467,289 @@ if (5 < 10) {
             assert_eq!(expected_type, tok);
         }
     }
+
+    #[test]
+    fn test_line_comments() {
+        let input = "// leading comment
+let a = 10 / 2; // trailing comment
+//
+a // comment at eof";
+
+        let tests = vec![
+            Token::Let,
+            Token::Ident("a".to_string()),
+            Token::Assign,
+            Token::Int(10),
+            Token::Slash,
+            Token::Int(2),
+            Token::Semicolon,
+            Token::Ident("a".to_string()),
+            Token::Eof,
+            Token::Eof,
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for expected_type in tests {
+            let tok = l.next_token();
+            assert_eq!(expected_type, tok);
+        }
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let tests = vec![
+            (
+                "1 /* single line */ + 2",
+                vec![Token::Int(1), Token::Plus, Token::Int(2), Token::Eof],
+            ),
+            (
+                "/* multi\n * line\n */ a / b",
+                vec![
+                    Token::Ident("a".to_string()),
+                    Token::Slash,
+                    Token::Ident("b".to_string()),
+                    Token::Eof,
+                ],
+            ),
+            (
+                "/* outer /* inner */ x */",
+                vec![
+                    Token::Ident("x".to_string()),
+                    Token::Asterisk,
+                    Token::Slash,
+                    Token::Eof,
+                ],
+            ),
+            ("/**/", vec![Token::Eof]),
+            (
+                "x /* never closed *",
+                vec![
+                    Token::Ident("x".to_string()),
+                    Token::Illegal("unterminated block comment".to_string()),
+                    Token::Eof,
+                ],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+
+            for expected_type in expected {
+                assert_eq!(expected_type, l.next_token());
+            }
+        }
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let mut l = Lexer::new("\"abc");
+
+        assert_eq!(
+            Token::Illegal("unterminated string literal".to_string()),
+            l.next_token()
+        );
+        assert_eq!(Token::Eof, l.next_token());
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tests = vec![
+            (r#""line1\nline2""#, "line1\nline2"),
+            (r#""a\tb""#, "a\tb"),
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""back\\slash""#, "back\\slash"),
+            (r#""\\n""#, "\\n"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(vec![Token::String(expected.to_string())], tokenize(input));
+        }
+
+        assert_eq!(
+            vec![
+                Token::Illegal("unknown escape sequence: \\q".to_string()),
+                Token::Semicolon
+            ],
+            tokenize(r#""a\qb\"c";"#)
+        );
+        assert_eq!(
+            vec![Token::Illegal("unterminated string literal".to_string())],
+            tokenize(r#""abc\"#)
+        );
+    }
+
+    #[test]
+    fn test_integer_literal_overflow() {
+        let input = "9223372036854775807; 99999999999999999999 + 1;";
+
+        let tests = vec![
+            Token::Int(i64::MAX),
+            Token::Semicolon,
+            Token::Illegal("integer literal too large: 99999999999999999999".to_string()),
+            Token::Plus,
+            Token::Int(1),
+            Token::Semicolon,
+            Token::Eof,
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for expected_type in tests {
+            let tok = l.next_token();
+            assert_eq!(expected_type, tok);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_float_literals() {
+        let input = "3.14; 10.0;";
+
+        let tests = vec![
+            Token::Float(3.14),
+            Token::Semicolon,
+            Token::Float(10.0),
+            Token::Semicolon,
+            Token::Eof,
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for expected_type in tests {
+            let tok = l.next_token();
+            assert_eq!(expected_type, tok);
+        }
+    }
+
+    #[test]
+    fn test_unicode_input() {
+        assert_eq!(
+            vec![
+                Token::Let,
+                Token::Ident("café".to_string()),
+                Token::Assign,
+                Token::String("🐒 maymun 🍌".to_string()),
+                Token::Semicolon,
+                Token::Ident("ÿ".to_string()),
+                Token::Ident("日本".to_string()),
+            ],
+            tokenize("let café = \"🐒 maymun 🍌\"; ÿ 日本")
+        );
+        assert_eq!(
+            vec![Token::Illegal("illegal character: 🐒".to_string())],
+            tokenize("🐒")
+        );
+    }
+
+    #[test]
+    fn test_unicode_identifier_followed_by_operator() {
+        let mut l = Lexer::new("çay+=1;\nşeker<<ñ");
+
+        let tests = vec![
+            (Token::Ident("çay".to_string()), 0, (1, 1)),
+            (Token::PlusEq, 4, (1, 4)),
+            (Token::Int(1), 6, (1, 6)),
+            (Token::Semicolon, 7, (1, 7)),
+            (Token::Ident("şeker".to_string()), 9, (2, 1)),
+            (Token::Shl, 15, (2, 6)),
+            (Token::Ident("ñ".to_string()), 17, (2, 8)),
+            (Token::Eof, 19, (2, 9)),
+            (Token::Eof, 19, (2, 9)),
+        ];
+
+        for (expected, offset, line_col) in tests {
+            assert_eq!(expected, l.next_token());
+            assert_eq!(offset, l.token_offset());
+            assert_eq!(line_col, l.line_col(l.token_offset()));
+        }
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let tests = vec![
+            ("1_000", vec![Token::Int(1000)]),
+            ("1_000_000", vec![Token::Int(1_000_000)]),
+            ("1_2_3", vec![Token::Int(123)]),
+            ("1_000.000_5", vec![Token::Float(1000.0005)]),
+            (
+                "1__0",
+                vec![Token::Illegal(
+                    "invalid digit separator in number: 1__0".to_string(),
+                )],
+            ),
+            (
+                "10_",
+                vec![Token::Illegal(
+                    "invalid digit separator in number: 10_".to_string(),
+                )],
+            ),
+            (
+                "1_.5",
+                vec![Token::Illegal(
+                    "invalid digit separator in number: 1_.5".to_string(),
+                )],
+            ),
+            ("_1", vec![Token::Ident("_".to_string()), Token::Int(1)]),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, tokenize(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            vec![
+                Token::Let,
+                Token::Ident("x".to_string()),
+                Token::Assign,
+                Token::Int(5),
+                Token::Semicolon,
+            ],
+            tokenize("let x = 5;")
+        );
+        assert_eq!(
+            vec![Token::Tilde, Token::Minus, Token::Int(1)],
+            tokenize("~-1")
+        );
+        assert_eq!(
+            vec![
+                Token::Le,
+                Token::Ge,
+                Token::Shl,
+                Token::Lt,
+                Token::Assign,
+                Token::Arrow,
+                Token::Eq
+            ],
+            tokenize("<= >= << < = => ==")
+        );
+        assert_eq!(
+            vec![
+                Token::Int(5),
+                Token::Dot,
+                Token::Ident("len".to_string()),
+                Token::Float(1.5),
+            ],
+            tokenize("5.len 1.5")
+        );
+        assert_eq!(
+            vec![
+                Token::Ellipsis,
+                Token::Ident("rest".to_string()),
+                Token::Dot,
+                Token::Dot,
+                Token::Ellipsis,
+                Token::Dot,
+            ],
+            tokenize("...rest .. ....")
+        );
+        assert_eq!(
+            vec![Token::Null, Token::Eq, Token::Ident("nullable".to_string()),],
+            tokenize("null == nullable")
+        );
+        assert_eq!(Vec::<Token>::new(), tokenize("  // nothing here"));
+    }
 }
@@ -1,7 +1,136 @@
 pub mod ast;
+pub mod cli;
 pub mod eval;
+pub mod fmt;
 pub mod lexer;
 pub mod object;
 pub mod parser;
 pub mod repl;
+#[cfg(test)]
+mod testing;
 pub mod token;
+
+use std::fmt::{Display, Formatter};
+
+use crate::eval::eval_program;
+use crate::lexer::Lexer;
+use crate::object::{Env, Object};
+use crate::parser::{ParseError, Parser};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaymunError {
+    // Every error the parser found; nothing was evaluated.
+    Parse(Vec<ParseError>),
+    // The error that stopped evaluation, prefixed with its line.
+    Runtime(String),
+}
+
+impl Display for MaymunError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaymunError::Parse(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            MaymunError::Runtime(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MaymunError {}
+
+// Parses and evaluates `source` in a fresh environment.
+pub fn run(source: &str) -> Result<Option<Object>, MaymunError> {
+    Interpreter::new().eval(source)
+}
+
+// Like `run`, but bindings made by one `eval` stay visible to the next.
+#[derive(Default)]
+pub struct Interpreter {
+    env: Env,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Option<Object>, MaymunError> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program();
+        if !parser.errors().is_empty() {
+            return Err(MaymunError::Parse(parser.errors()));
+        }
+
+        match eval_program(program, &self.env) {
+            Some(Object::Error(msg)) => Err(MaymunError::Runtime(msg)),
+            result => Ok(result),
+        }
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        assert_eq!(Ok(Some(Object::Integer(6))), run("let x = 5; x + 1"));
+        assert_eq!(Ok(None), run("let x = 5;"));
+        assert_eq!(
+            Err(MaymunError::Runtime(
+                "line 1: identifier not found: y".to_string()
+            )),
+            run("y")
+        );
+    }
+
+    #[test]
+    fn test_interpreter_keeps_bindings() {
+        let mut interpreter = Interpreter::new();
+
+        assert_eq!(Ok(None), interpreter.eval("let x = 5"));
+        assert_eq!(Ok(Some(Object::Integer(6))), interpreter.eval("x + 1"));
+        assert!(matches!(
+            interpreter.eval("let = 1"),
+            Err(MaymunError::Parse(_))
+        ));
+        assert!(matches!(
+            interpreter.eval("x + true"),
+            Err(MaymunError::Runtime(_))
+        ));
+        assert_eq!(Ok(None), interpreter.eval("let inc = fn(n) { n + x }"));
+        assert_eq!(Ok(Some(Object::Integer(7))), interpreter.eval("inc(2)"));
+        assert_eq!(
+            Some(&Object::Integer(5)),
+            interpreter.env().borrow().get("x").as_deref()
+        );
+    }
+
+    #[test]
+    fn test_run_parse_errors() {
+        assert_eq!(
+            Err(MaymunError::Parse(vec![ParseError {
+                message: "expected next token to be Assign, got Int(5) instead".to_string(),
+                line: 1,
+                col: 7,
+            }])),
+            run("let x 5;")
+        );
+    }
+
+    #[test]
+    fn test_run_runtime_errors() {
+        let err = run("let x = 1;\nx + true").unwrap_err();
+
+        assert_eq!(
+            MaymunError::Runtime("line 2: type mismatch: INTEGER + BOOLEAN".to_string()),
+            err
+        );
+        assert_eq!("line 2: type mismatch: INTEGER + BOOLEAN", err.to_string());
+    }
+}
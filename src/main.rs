@@ -1,11 +1,15 @@
-use std::io;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::process;
 
-use maymun_lang::repl;
+use maymun_lang::cli;
 
-fn main() -> io::Result<()> {
-    println!("Hello! This is the Maymun programming language!");
-    println!("Feel free to type in commands");
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Piped input is a program to run, not a REPL session.
+    if args.is_empty() && !io::stdin().is_terminal() {
+        args.push("-".to_string());
+    }
 
-    repl::start(io::stdin(), io::stdout());
-    Ok(())
+    process::exit(cli::run(&args, io::stdin(), io::stdout(), io::stderr()));
 }
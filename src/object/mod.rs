@@ -1,25 +1,480 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::ast::{parameter_list, statement_list, BlockStatement, Identifier};
+
+// Shorthands for the values that carry no payload beyond their variant. Being
+// consts, every use is a fresh copy, so compare them by value, not identity.
+pub const TRUE: Object = Object::Boolean(true);
+pub const FALSE: Object = Object::Boolean(false);
+pub const NULL: Object = Object::Null;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Object {
     Integer(i64),
+    Float(f64),
     Boolean(bool),
+    // Strings, arrays and hashes are shared, and copied only when changed while
+    // something else still holds them.
+    String(Rc<String>),
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<HashPairs>),
     Null,
     Return(Box<Object>),
+    // Signals raised by `break` and `continue`, caught by the enclosing loop.
+    Break,
+    Continue,
     Error(String),
+    Function(Function),
+    Builtin(Builtin),
+}
+
+impl Object {
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(i) => Some(HashKey::Integer(*i)),
+            Object::Boolean(b) => Some(HashKey::Boolean(*b)),
+            Object::String(s) => Some(HashKey::String(s.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Null => "NULL",
+            Object::Return(_) => "RETURN_VALUE",
+            Object::Break => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::Error(_) => "ERROR",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+        }
+    }
+
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::Integer(i) => i.to_string(),
+            Object::Float(f) => format!("{:?}", f),
+            Object::Boolean(b) => b.to_string(),
+            Object::String(s) => format!("\"{}\"", s),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.inspect()).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", Object::from(k.clone()).inspect(), v.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Object::Null => "null".to_string(),
+            Object::Return(o) => o.inspect(),
+            Object::Break => "break".to_string(),
+            Object::Continue => "continue".to_string(),
+            Object::Error(msg) => format!("ERROR: {}", msg),
+            Object::Function(function) => {
+                format!(
+                    "fn({}) {{ {} }}",
+                    parameter_list(&function.parameters, &function.rest),
+                    statement_list(&function.body)
+                )
+            }
+            Object::Builtin(_) => "builtin function".to_string(),
+        }
+    }
 }
 
 impl Display for Object {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Object::Integer(i) => write!(f, "Integer({})", i),
+            Object::Float(float) => write!(f, "Float({:?})", float),
             Object::Boolean(b) => write!(f, "Boolean({})", b),
+            Object::String(s) => write!(f, "String({})", s),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "Array([{}])", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", Object::from(k.clone()), v))
+                    .collect();
+                write!(f, "Hash({{{}}})", pairs.join(", "))
+            }
             Object::Null => write!(f, "Null"),
             Object::Return(o) => write!(f, "Return({})", o),
+            Object::Break => write!(f, "Break"),
+            Object::Continue => write!(f, "Continue"),
             Object::Error(msg) => write!(f, "Error({})", msg),
+            Object::Function(function) => write!(f, "{:?}", function),
+            Object::Builtin(builtin) => write!(f, "Builtin({})", builtin.name),
+        }
+    }
+}
+
+impl From<bool> for Object {
+    fn from(b: bool) -> Self {
+        if b {
+            TRUE
+        } else {
+            FALSE
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl From<HashKey> for Object {
+    fn from(key: HashKey) -> Self {
+        match key {
+            HashKey::Integer(i) => Object::Integer(i),
+            HashKey::Boolean(b) => Object::from(b),
+            HashKey::String(s) => Object::String(s.into()),
+        }
+    }
+}
+
+// The pairs of a hash, kept in the order their keys were first inserted so that
+// printing a hash always gives the same result. Two hashes are equal when they
+// hold the same pairs, whatever their order.
+#[derive(Clone, Debug, Default)]
+pub struct HashPairs {
+    entries: Vec<(HashKey, Object)>,
+    positions: HashMap<HashKey, usize>,
+}
+
+impl HashPairs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Object> {
+        self.positions.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &HashKey) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    // Replacing the value of an existing key keeps the key where it was.
+    pub fn insert(&mut self, key: HashKey, value: Object) -> Option<Object> {
+        match self.positions.get(&key) {
+            Some(&i) => Some(std::mem::replace(&mut self.entries[i].1, value)),
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    // Takes time linear in the size of the hash, since every later entry moves up
+    // a place to keep the order.
+    pub fn remove(&mut self, key: &HashKey) -> Option<Object> {
+        let i = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for position in self.positions.values_mut() {
+            if *position > i {
+                *position -= 1;
+            }
+        }
+
+        Some(value)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &HashKey> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&HashKey, &Object)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl PartialEq for HashPairs {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl FromIterator<(HashKey, Object)> for HashPairs {
+    fn from_iter<I: IntoIterator<Item = (HashKey, Object)>>(iter: I) -> Self {
+        let mut pairs = HashPairs::new();
+        for (key, value) in iter {
+            pairs.insert(key, value);
         }
+
+        pairs
     }
 }
 
-pub type Environment = HashMap<String, Object>;
+impl<const N: usize> From<[(HashKey, Object); N]> for HashPairs {
+    fn from(pairs: [(HashKey, Object); N]) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+impl IntoIterator for HashPairs {
+    type Item = (HashKey, Object);
+    type IntoIter = std::vec::IntoIter<(HashKey, Object)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+#[derive(Clone)]
+pub struct Function {
+    pub parameters: Vec<Identifier>,
+    pub rest: Option<Identifier>,
+    pub body: Rc<BlockStatement>,
+    pub env: Env,
+}
+
+// A function's environment usually contains the function itself, so neither
+// formatting nor comparison may descend into it.
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Function({})",
+            parameter_list(&self.parameters, &self.rest)
+        )
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.body, &other.body) && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+// What a builtin may ask of the evaluation that calls it. The evaluator
+// implements it, which keeps this module from depending on the evaluator.
+pub trait BuiltinContext {
+    // Where `puts` and `print` write.
+    fn output(&mut self) -> &mut dyn Write;
+
+    // Calls a function or builtin with `args`.
+    fn call(&mut self, function: &Object, args: Vec<Object>) -> Object;
+}
+
+pub type BuiltinFunction = fn(&mut dyn BuiltinContext, Vec<Object>) -> Object;
+
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFunction,
+}
+
+impl Debug for Builtin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+// Values are shared rather than owned so that reading a binding never has to
+// copy a large array or string.
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Rc<Object>>,
+    outer: Option<Env>,
+}
+
+pub type Env = Rc<RefCell<Environment>>;
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn new_enclosed(outer: Env) -> Self {
+        Self {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<Object>> {
+        match self.store.get(name) {
+            Some(o) => Some(Rc::clone(o)),
+            None => self
+                .outer
+                .as_ref()
+                .and_then(|outer| outer.borrow().get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: Object) {
+        self.store.insert(name.to_string(), Rc::new(value));
+    }
+
+    // Bindings of this scope only, sorted by name.
+    pub fn entries(&self) -> Vec<(String, Rc<Object>)> {
+        let mut entries: Vec<(String, Rc<Object>)> = self
+            .store
+            .iter()
+            .map(|(name, value)| (name.clone(), Rc::clone(value)))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        entries
+    }
+
+    // Runs `f` on the value bound to `name` in the nearest scope that declares
+    // it, changing it in place. Returns `None` if no scope does.
+    pub fn update<R>(&mut self, name: &str, f: impl FnOnce(&mut Object) -> R) -> Option<R> {
+        if let Some(slot) = self.store.get_mut(name) {
+            return Some(f(Rc::make_mut(slot)));
+        }
+
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().update(name, f),
+            None => None,
+        }
+    }
+
+    // Rebinds `name` in the nearest scope that declares it. Returns false if no
+    // scope does, leaving every store untouched.
+    pub fn assign(&mut self, name: &str, value: Object) -> bool {
+        if let Some(slot) = self.store.get_mut(name) {
+            match Rc::get_mut(slot) {
+                Some(shared) => *shared = value,
+                None => *slot = Rc::new(value),
+            }
+            return true;
+        }
+
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
+
+impl From<Environment> for Env {
+    fn from(env: Environment) -> Self {
+        Rc::new(RefCell::new(env))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect() {
+        let mut hash = HashPairs::new();
+        hash.insert(HashKey::String("a".to_string()), Object::Integer(1));
+
+        let tests = vec![
+            (Object::Integer(5), "5"),
+            (Object::Integer(-5), "-5"),
+            (Object::Float(2.5), "2.5"),
+            (Object::Float(2.0), "2.0"),
+            (TRUE, "true"),
+            (NULL, "null"),
+            (Object::String("hello".to_string().into()), "\"hello\""),
+            (
+                Object::Array(
+                    vec![
+                        Object::Integer(1),
+                        Object::String("two".to_string().into()),
+                        Object::Array(vec![FALSE].into()),
+                    ]
+                    .into(),
+                ),
+                "[1, \"two\", [false]]",
+            ),
+            (Object::Array(vec![].into()), "[]"),
+            (Object::Hash(hash.into()), "{\"a\": 1}"),
+            (Object::Error("boom".to_string()), "ERROR: boom"),
+        ];
+
+        for (object, expected) in tests {
+            assert_eq!(expected, object.inspect());
+        }
+    }
+
+    #[test]
+    fn test_environment_shares_values() {
+        let mut env = Environment::new();
+        env.set("a", Object::Array(vec![Object::Integer(0); 10_000].into()));
+
+        let first = env.get("a").unwrap();
+        let second = env.get("a").unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(3, Rc::strong_count(&first));
+
+        let inner: Env = Environment::new_enclosed(env.into()).into();
+        let third = inner.borrow().get("a").unwrap();
+        assert!(Rc::ptr_eq(&first, &third));
+    }
+
+    #[test]
+    fn test_hash_keeps_insertion_order() {
+        let mut pairs = HashPairs::new();
+        for key in ["z", "a", "m", "b"] {
+            pairs.insert(
+                HashKey::String(key.to_string()),
+                Object::Integer(key.len() as i64),
+            );
+        }
+        pairs.insert(HashKey::String("a".to_string()), Object::Integer(2));
+        pairs.remove(&HashKey::String("m".to_string()));
+
+        assert_eq!(
+            "{\"z\": 1, \"a\": 2, \"b\": 1}",
+            Object::Hash(pairs.clone().into()).inspect()
+        );
+        assert_eq!(
+            Some(&Object::Integer(1)),
+            pairs.get(&HashKey::String("b".to_string()))
+        );
+
+        let reversed: HashPairs = pairs.clone().into_iter().rev().collect();
+        assert_eq!(pairs, reversed);
+    }
+
+    #[test]
+    fn test_display_keeps_type_names() {
+        assert_eq!("Integer(5)", Object::Integer(5).to_string());
+        assert_eq!(
+            "String(a)",
+            Object::String("a".to_string().into()).to_string()
+        );
+    }
+}
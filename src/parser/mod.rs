@@ -1,31 +1,61 @@
-use crate::ast::{BlockStatement, Expression, Identifier, Program, Statement};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use crate::ast::{BlockStatement, Expression, Identifier, Pattern, Program, Statement};
 use crate::lexer::Lexer;
 use crate::token::Token;
 
 #[derive(PartialEq, PartialOrd)]
-enum Precedence {
+pub(crate) enum Precedence {
     Lowest,
+    Assign,      // a = b, a += b, ...
+    Ternary,     // a ? b : c
     Equals,      // ==
     LessGreater, // > or <
+    BitOr,       // |
+    BitXor,      // ^
+    BitAnd,      // &
+    Shift,       // << or >>
     Sum,         // +
     Product,     // *
-    Prefix,      // -x or !x
-    Call,        // my_function(x)
+    Prefix,      // -x, !x or ~x
+    Call,        // my_function(x), x.method() or x[i]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     cur_token: Token,
     peek_token: Token,
-    errors: Vec<String>,
+    cur_offset: usize,
+    peek_offset: usize,
+    cur_line: usize,
+    peek_line: usize,
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: Lexer<'a>) -> Self {
         let mut p = Self {
             lexer,
-            cur_token: Token::Illegal,
-            peek_token: Token::Illegal,
+            cur_token: Token::Eof,
+            peek_token: Token::Eof,
+            cur_offset: 0,
+            peek_offset: 0,
+            cur_line: 1,
+            peek_line: 1,
             errors: vec![],
         };
 
@@ -38,8 +68,9 @@ impl<'a> Parser<'a> {
         let mut program = Program::new();
 
         while self.cur_token != Token::Eof {
+            let line = self.cur_line;
             if let Some(stmt) = self.parse_statement() {
-                program.push(stmt);
+                program.push(stmt, line);
             }
             self.next_token();
         }
@@ -47,23 +78,165 @@ impl<'a> Parser<'a> {
         program
     }
 
-    pub fn errors(&self) -> Vec<String> {
-        return self.errors.clone();
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
     }
 
     fn next_token(&mut self) {
         self.cur_token = self.peek_token.clone();
+        self.cur_offset = self.peek_offset;
+        self.cur_line = self.peek_line;
         self.peek_token = self.lexer.next_token();
+        self.peek_offset = self.lexer.token_offset();
+        self.peek_line = self.lexer.token_line();
+    }
+
+    fn error_at(&mut self, offset: usize, message: String) {
+        let (line, col) = self.lexer.line_col(offset);
+        self.errors.push(ParseError { message, line, col });
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
         match self.cur_token {
             Token::Let => self.parse_let_statement(),
             Token::Return => self.parse_return_statement(),
+            Token::While => self.parse_while_statement(),
+            Token::For => self.parse_for_statement(),
+            Token::Break => Some(self.parse_loop_control(Statement::Break)),
+            Token::Continue => Some(self.parse_loop_control(Statement::Continue)),
+            Token::Lbrace if !self.starts_hash_literal() => {
+                Some(Statement::Block(self.parse_block_statement()))
+            }
+            Token::Function => match &self.peek_token {
+                Token::Ident(name) => {
+                    let name = name.clone();
+                    self.parse_function_declaration(name)
+                }
+                _ => self.parse_expression_statement(),
+            },
             _ => self.parse_expression_statement(),
         }
     }
 
+    // A `{` at the start of a statement opens a hash literal if it is closed right
+    // away or its first expression is followed by a `:`, as in `{"a": 1}`, and a
+    // block otherwise. The `:` of a ternary doesn't count. Only the tokens up to
+    // the end of that first expression are looked at.
+    fn starts_hash_literal(&self) -> bool {
+        if self.peek_token == Token::Rbrace {
+            return true;
+        }
+
+        let mut lexer = self.lexer.clone();
+        let mut token = self.peek_token.clone();
+        let mut depth = 0;
+        let mut ternaries = 0;
+        loop {
+            match token {
+                Token::Lparen | Token::Lbracket | Token::Lbrace => depth += 1,
+                Token::Rparen | Token::Rbracket | Token::Rbrace if depth > 0 => depth -= 1,
+                Token::Eof | Token::Illegal(_) => return false,
+                _ if depth > 0 => {}
+                Token::Question => ternaries += 1,
+                Token::Colon if ternaries > 0 => ternaries -= 1,
+                Token::Colon => return true,
+                Token::Semicolon
+                | Token::Rparen
+                | Token::Rbracket
+                | Token::Rbrace
+                | Token::Let
+                | Token::Return
+                | Token::While
+                | Token::For
+                | Token::Break
+                | Token::Continue => return false,
+                _ => {}
+            }
+            token = lexer.next_token();
+        }
+    }
+
+    fn parse_while_statement(&mut self) -> Option<Statement> {
+        if !self.expect_peek(Token::Lparen) {
+            return None;
+        }
+
+        self.next_token();
+        let cond = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(Token::Rparen) {
+            return None;
+        }
+
+        if !self.expect_peek(Token::Lbrace) {
+            return None;
+        }
+
+        Some(Statement::While(cond, self.parse_block_statement()))
+    }
+
+    // Both `for (x in xs)` and `for (init; condition; post)` start with `for (`;
+    // only the first has `in` right after a name.
+    fn parse_for_statement(&mut self) -> Option<Statement> {
+        if !self.expect_peek(Token::Lparen) {
+            return None;
+        }
+        self.next_token();
+
+        let ident = match (&self.cur_token, &self.peek_token) {
+            (Token::Ident(ident), Token::In) => ident.clone(),
+            _ => return self.parse_c_for_statement(),
+        };
+        self.next_token();
+
+        self.next_token();
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(Token::Rparen) {
+            return None;
+        }
+
+        if !self.expect_peek(Token::Lbrace) {
+            return None;
+        }
+
+        Some(Statement::ForIn(
+            ident,
+            iterable,
+            self.parse_block_statement(),
+        ))
+    }
+
+    fn parse_c_for_statement(&mut self) -> Option<Statement> {
+        let init = match self.cur_token {
+            Token::Let => self.parse_let_statement()?,
+            _ => self.parse_expression_statement()?,
+        };
+        if self.cur_token != Token::Semicolon {
+            self.peek_error(Token::Semicolon);
+            return None;
+        }
+
+        self.next_token();
+        let cond = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(Token::Semicolon) {
+            return None;
+        }
+
+        self.next_token();
+        let post = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(Token::Rparen) || !self.expect_peek(Token::Lbrace) {
+            return None;
+        }
+
+        Some(Statement::ForC(
+            Box::new(init),
+            cond,
+            Box::new(Statement::Expression(post)),
+            self.parse_block_statement(),
+        ))
+    }
+
     fn parse_let_statement(&mut self) -> Option<Statement> {
         match self.peek_token.clone() {
             Token::Ident(ident) => {
@@ -74,7 +247,7 @@ impl<'a> Parser<'a> {
                 }
 
                 self.next_token();
-                let expr = self.parse_expression(Precedence::Lowest).unwrap();
+                let expr = self.parse_expression(Precedence::Lowest)?;
 
                 while self.peek_token == Token::Semicolon {
                     self.next_token();
@@ -89,51 +262,147 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // `fn name(params) { body }` is sugar for `let name = fn(params) { body };`.
+    // The peek token is `name`.
+    fn parse_function_declaration(&mut self, name: Identifier) -> Option<Statement> {
+        self.next_token();
+        let function = self.parse_function_literal()?;
+
+        while self.peek_token == Token::Semicolon {
+            self.next_token();
+        }
+
+        Some(Statement::Let(name, function))
+    }
+
+    // Parses an assignment to `target`, whose operator is the peek token. The
+    // value is parsed at the lowest precedence, which makes assignment
+    // right-associative: `a = b = 5` assigns `b = 5` to `a`. A compound operator
+    // on a name is sugar: `x += v` assigns `x + v`. On an element it gets a node
+    // of its own, since repeating the index would evaluate it twice.
+    //
+    // Only a name or an element of a bound array or hash can be assigned, as in
+    // `a[0] = 1`. The value is parsed even for any other target so it isn't
+    // reported again.
+    fn parse_assign_expression(&mut self, target: Expression) -> Option<Expression> {
+        let assignable = match &target {
+            Expression::Literal(_) => true,
+            Expression::Index(left, _) => matches!(&**left, Expression::Literal(_)),
+            _ => false,
+        };
+        if !assignable {
+            self.error_at(self.peek_offset, format!("cannot assign to {}", target));
+        }
+
+        self.next_token();
+        let op = match self.cur_token {
+            Token::PlusEq => Some("+"),
+            Token::MinusEq => Some("-"),
+            Token::AsteriskEq => Some("*"),
+            Token::SlashEq => Some("/"),
+            _ => None,
+        };
+
+        self.next_token();
+        let value = Box::new(self.parse_expression(Precedence::Lowest)?);
+
+        match (target, op) {
+            (Expression::Literal(name), None) => Some(Expression::Assign(name, value)),
+            (Expression::Literal(name), Some(op)) => {
+                let current = Box::new(Expression::Literal(name.clone()));
+                let value = Expression::Infix(current, op.to_string(), value);
+                Some(Expression::Assign(name, Box::new(value)))
+            }
+            (Expression::Index(left, index), op) => match (*left, op) {
+                (Expression::Literal(name), None) => {
+                    Some(Expression::IndexAssign(name, index, value))
+                }
+                (Expression::Literal(name), Some(op)) => Some(Expression::CompoundIndexAssign(
+                    name,
+                    index,
+                    op.to_string(),
+                    value,
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     fn parse_return_statement(&mut self) -> Option<Statement> {
         self.next_token();
 
-        let expr = self.parse_expression(Precedence::Lowest).unwrap();
+        let expr = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token == Token::Semicolon {
+            self.next_token();
+        }
+
+        Some(Statement::Return(expr))
+    }
 
+    fn parse_loop_control(&mut self, stmt: Statement) -> Statement {
         if self.peek_token == Token::Semicolon {
             self.next_token();
         }
 
-        return Some(Statement::Return(expr));
+        stmt
     }
 
+    // The semicolon after an expression may be left out at the end of a block or
+    // a line, or after a closing brace as in `if (x) { y } z`, but not between two
+    // expressions on the same line, as in `5 5`. An illegal token is left for the
+    // next statement to report, so it isn't reported twice.
     fn parse_expression_statement(&mut self) -> Option<Statement> {
         let expr = self.parse_expression(Precedence::Lowest);
         if let Some(expr) = expr {
-            if self.peek_token == Token::Semicolon {
-                self.next_token();
+            match self.peek_token {
+                Token::Semicolon => self.next_token(),
+                Token::Rbrace | Token::Eof | Token::Illegal(_) => {}
+                _ if self.cur_token == Token::Rbrace => {}
+                _ if self.peek_line > self.cur_line => {}
+                _ => {
+                    let message = format!("unexpected token {:?}", self.peek_token);
+                    self.error_at(self.peek_offset, message);
+                }
             }
 
             return Some(Statement::Expression(expr));
         }
 
-        return None;
+        None
     }
 
     fn parse_expression(&mut self, pre: Precedence) -> Option<Expression> {
         let mut left_expr = match &self.cur_token {
             Token::Ident(ident) => Expression::Literal(ident.to_owned()),
             Token::Int(i) => Expression::Int(*i),
-            Token::True | Token::False => Expression::Boolean(&self.cur_token == &Token::True),
+            Token::Float(float) => Expression::Float(*float),
+            Token::String(string) => Expression::String(string.to_owned()),
+            Token::True | Token::False => Expression::Boolean(self.cur_token == Token::True),
+            Token::Null => Expression::Null,
             Token::Lparen => {
                 self.next_token();
 
-                let expr = self.parse_expression(Precedence::Lowest).unwrap();
-                if !self.expect_peek(Token::Rparen) {
-                    return None;
-                }
+                if is_infix_operator(&self.cur_token) && self.peek_token == Token::Rparen {
+                    let op = self.cur_token.to_string();
+                    self.next_token();
+
+                    Expression::OperatorFunction(op)
+                } else {
+                    let expr = self.parse_expression(Precedence::Lowest)?;
+                    if !self.expect_peek(Token::Rparen) {
+                        return None;
+                    }
 
-                expr
+                    expr
+                }
             }
-            Token::Bang | Token::Minus => {
-                let op = (&self.cur_token).to_string();
+            Token::Bang | Token::Minus | Token::Tilde => {
+                let op = self.cur_token.to_string();
                 self.next_token();
 
-                let expr = self.parse_expression(Precedence::Prefix).unwrap();
+                let expr = self.parse_expression(Precedence::Prefix)?;
                 Expression::Prefix(op, Box::new(expr))
             }
             Token::If => {
@@ -142,7 +411,7 @@ impl<'a> Parser<'a> {
                 }
 
                 self.next_token();
-                let cond = self.parse_expression(Precedence::Lowest).unwrap();
+                let cond = self.parse_expression(Precedence::Lowest)?;
 
                 if !self.expect_peek(Token::Rparen) {
                     return None;
@@ -166,51 +435,100 @@ impl<'a> Parser<'a> {
                     Expression::If(Box::new(cond), conseq, None)
                 }
             }
-            Token::Function => {
-                if !self.expect_peek(Token::Lparen) {
-                    return None;
-                }
-
-                let parameters = self.parse_function_parameters();
-
+            Token::Do => {
                 if !self.expect_peek(Token::Lbrace) {
                     return None;
                 }
 
-                Expression::Function(parameters, self.parse_block_statement())
+                Expression::Do(self.parse_block_statement())
+            }
+            Token::Match => self.parse_match_expression()?,
+            Token::Function => self.parse_function_literal()?,
+            Token::Lbracket => Expression::Array(self.parse_expression_list(Token::Rbracket)?),
+            Token::Lbrace => self.parse_hash_literal()?,
+            Token::Illegal(reason) => {
+                self.error_at(self.cur_offset, reason.to_owned());
+                return None;
             }
             _ => {
-                self.errors.push(format!(
-                    "undefined expression for {} found",
-                    &self.cur_token.to_string()
-                ));
+                self.error_at(
+                    self.cur_offset,
+                    format!("undefined expression for {} found", &self.cur_token),
+                );
                 return None;
             }
         };
 
         while self.peek_token != Token::Semicolon && pre < self.precedence_for(&self.peek_token) {
             left_expr = match &self.peek_token {
-                Token::Plus
-                | Token::Minus
-                | Token::Slash
-                | Token::Asterisk
-                | Token::Eq
-                | Token::NotEq
-                | Token::Lt
-                | Token::Gt => {
+                token if is_assign_operator(token) => self.parse_assign_expression(left_expr)?,
+                token if is_infix_operator(token) => {
                     self.next_token();
 
-                    let op = (&self.cur_token).to_string();
+                    let op = self.cur_token.to_string();
                     let cur_pre = self.precedence_for(&self.cur_token);
                     self.next_token();
 
-                    let expr = self.parse_expression(cur_pre).unwrap();
+                    let expr = self.parse_expression(cur_pre)?;
                     Expression::Infix(Box::new(left_expr), op, Box::new(expr))
                 }
                 Token::Lparen => {
                     self.next_token();
 
-                    Expression::Call(Box::new(left_expr), self.parse_call_arguments())
+                    let arguments = self.parse_expression_list(Token::Rparen)?;
+                    Expression::Call(
+                        Box::new(left_expr),
+                        arguments.into_iter().map(Box::new).collect(),
+                    )
+                }
+                Token::Lbracket => {
+                    self.next_token();
+                    self.next_token();
+
+                    let index = self.parse_expression(Precedence::Lowest)?;
+                    if !self.expect_peek(Token::Rbracket) {
+                        return None;
+                    }
+
+                    Expression::Index(Box::new(left_expr), Box::new(index))
+                }
+                Token::Question => {
+                    self.next_token();
+                    self.next_token();
+
+                    let conseq = self.parse_expression(Precedence::Lowest)?;
+                    if !self.expect_peek(Token::Colon) {
+                        return None;
+                    }
+                    self.next_token();
+
+                    // Parsing the alternative at the lowest precedence makes the
+                    // operator right-associative: `a ? b : c ? d : e`.
+                    let alter = self.parse_expression(Precedence::Lowest)?;
+                    Expression::Ternary(Box::new(left_expr), Box::new(conseq), Box::new(alter))
+                }
+                Token::Dot => {
+                    self.next_token();
+
+                    let Token::Ident(method) = self.peek_token.clone() else {
+                        let message = format!(
+                            "expected a method name after ., got {:?} instead",
+                            self.peek_token
+                        );
+                        self.error_at(self.peek_offset, message);
+                        return None;
+                    };
+                    self.next_token();
+
+                    if !self.expect_peek(Token::Lparen) {
+                        return None;
+                    }
+
+                    Expression::MethodCall(
+                        Box::new(left_expr),
+                        method,
+                        self.parse_expression_list(Token::Rparen)?,
+                    )
                 }
                 _ => return Some(left_expr),
             }
@@ -219,58 +537,171 @@ impl<'a> Parser<'a> {
         Some(left_expr)
     }
 
-    fn parse_function_parameters(&mut self) -> Vec<Identifier> {
+    // Parses the parameter list and body that follow `fn` or a declared name.
+    fn parse_function_literal(&mut self) -> Option<Expression> {
+        if !self.expect_peek(Token::Lparen) {
+            return None;
+        }
+
+        let (parameters, rest) = self.parse_function_parameters();
+
+        if !self.expect_peek(Token::Lbrace) {
+            return None;
+        }
+
+        Some(Expression::Function(
+            parameters,
+            rest,
+            Rc::new(self.parse_block_statement()),
+        ))
+    }
+
+    // A `...rest` parameter may only come last.
+    fn parse_function_parameters(&mut self) -> (Vec<Identifier>, Option<Identifier>) {
         let mut identifiers: Vec<Identifier> = vec![];
         if self.peek_token == Token::Rparen {
             self.next_token();
 
-            return identifiers;
+            return (identifiers, None);
         }
 
         self.next_token();
 
-        match &self.cur_token {
-            Token::Ident(ident) => identifiers.push(ident.to_owned()),
-            _ => {}
-        }
-
-        while self.peek_token == Token::Comma {
-            self.next_token();
-            self.next_token();
-
+        loop {
             match &self.cur_token {
                 Token::Ident(ident) => identifiers.push(ident.to_owned()),
+                Token::Ellipsis => {
+                    let Token::Ident(rest) = self.peek_token.clone() else {
+                        self.peek_error(Token::Ident("".to_string()));
+                        return (vec![], None);
+                    };
+                    self.next_token();
+
+                    if !self.expect_peek(Token::Rparen) {
+                        return (vec![], None);
+                    }
+
+                    return (identifiers, Some(rest));
+                }
                 _ => {}
             }
+
+            if self.peek_token != Token::Comma {
+                break;
+            }
+            self.next_token();
+            self.next_token();
         }
 
         if !self.expect_peek(Token::Rparen) {
-            return vec![];
+            return (vec![], None);
         }
 
-        identifiers
+        (identifiers, None)
     }
 
-    fn parse_call_arguments(&mut self) -> Vec<Box<Expression>> {
+    fn parse_expression_list(&mut self, end: Token) -> Option<Vec<Expression>> {
         let mut args = vec![];
         self.next_token();
-        if self.cur_token == Token::Rparen {
-            return args;
+        if self.cur_token == end {
+            return Some(args);
         }
 
-        args.push(Box::new(self.parse_expression(Precedence::Lowest).unwrap()));
+        args.push(self.parse_expression(Precedence::Lowest)?);
         while self.peek_token == Token::Comma {
             self.next_token();
             self.next_token();
 
-            args.push(Box::new(self.parse_expression(Precedence::Lowest).unwrap()));
+            args.push(self.parse_expression(Precedence::Lowest)?);
         }
 
-        if !self.expect_peek(Token::Rparen) {
-            return vec![];
+        if !self.expect_peek(end) {
+            return None;
+        }
+
+        Some(args)
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<Expression> {
+        let mut pairs = vec![];
+        while self.peek_token != Token::Rbrace {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+
+            if !self.expect_peek(Token::Colon) {
+                return None;
+            }
+
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+
+            if self.peek_token != Token::Rbrace && !self.expect_peek(Token::Comma) {
+                return None;
+            }
+        }
+
+        if !self.expect_peek(Token::Rbrace) {
+            return None;
+        }
+
+        Some(Expression::Hash(pairs))
+    }
+
+    fn parse_match_expression(&mut self) -> Option<Expression> {
+        if !self.expect_peek(Token::Lparen) {
+            return None;
+        }
+
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(Token::Rparen) || !self.expect_peek(Token::Lbrace) {
+            return None;
+        }
+
+        let mut arms = vec![];
+        while self.peek_token != Token::Rbrace {
+            self.next_token();
+            let pattern = self.parse_pattern()?;
+
+            if !self.expect_peek(Token::Arrow) || !self.expect_peek(Token::Lbrace) {
+                return None;
+            }
+            arms.push((pattern, self.parse_block_statement()));
+
+            if self.peek_token != Token::Rbrace && !self.expect_peek(Token::Comma) {
+                return None;
+            }
+        }
+
+        if !self.expect_peek(Token::Rbrace) {
+            return None;
         }
 
-        args
+        Some(Expression::Match(Box::new(value), arms))
+    }
+
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        let pattern = match (&self.cur_token, &self.peek_token) {
+            (Token::Int(int), _) => Pattern::Int(*int),
+            (Token::Minus, Token::Int(int)) => {
+                let int = -int;
+                self.next_token();
+                Pattern::Int(int)
+            }
+            (Token::True, _) => Pattern::Boolean(true),
+            (Token::False, _) => Pattern::Boolean(false),
+            (Token::String(string), _) => Pattern::String(string.clone()),
+            (Token::Ident(ident), _) if ident == "_" => Pattern::Wildcard,
+            _ => {
+                let message = format!("expected a pattern, got {} instead", self.cur_token);
+                self.error_at(self.cur_offset, message);
+                return None;
+            }
+        };
+
+        Some(pattern)
     }
 
     fn parse_block_statement(&mut self) -> BlockStatement {
@@ -284,6 +715,15 @@ impl<'a> Parser<'a> {
             self.next_token();
         }
 
+        if self.cur_token == Token::Eof {
+            let message = format!(
+                "expected next token to be {:?}, got {:?} instead",
+                Token::Rbrace,
+                Token::Eof
+            );
+            self.error_at(self.cur_offset, message);
+        }
+
         block_stmt
     }
 
@@ -294,14 +734,20 @@ impl<'a> Parser<'a> {
         }
 
         self.peek_error(token);
-        return false;
+        false
     }
 
     fn precedence_for(&self, token: &Token) -> Precedence {
         match token {
-            Token::Lparen => Precedence::Call,
+            Token::Lparen | Token::Dot | Token::Lbracket => Precedence::Call,
+            Token::Question => Precedence::Ternary,
+            token if is_assign_operator(token) => Precedence::Assign,
             Token::Eq | Token::NotEq => Precedence::Equals,
-            Token::Lt | Token::Gt => Precedence::LessGreater,
+            Token::Lt | Token::Gt | Token::Le | Token::Ge => Precedence::LessGreater,
+            Token::BitOr => Precedence::BitOr,
+            Token::Caret => Precedence::BitXor,
+            Token::BitAnd => Precedence::BitAnd,
+            Token::Shl | Token::Shr => Precedence::Shift,
             Token::Plus | Token::Minus => Precedence::Sum,
             Token::Slash | Token::Asterisk => Precedence::Product,
             _ => Precedence::Lowest,
@@ -309,13 +755,57 @@ impl<'a> Parser<'a> {
     }
 
     fn peek_error(&mut self, token: Token) {
-        self.errors.push(format!(
+        let message = format!(
             "expected next token to be {:?}, got {:?} instead",
             token, self.peek_token
-        ))
+        );
+        self.error_at(self.peek_offset, message);
     }
 }
 
+// Mirrors `precedence_for` for operators that have already been parsed into the AST.
+pub(crate) fn operator_precedence(op: &str) -> Precedence {
+    match op {
+        "==" | "!=" => Precedence::Equals,
+        "<" | ">" | "<=" | ">=" => Precedence::LessGreater,
+        "|" => Precedence::BitOr,
+        "^" => Precedence::BitXor,
+        "&" => Precedence::BitAnd,
+        "<<" | ">>" => Precedence::Shift,
+        "+" | "-" => Precedence::Sum,
+        "*" | "/" => Precedence::Product,
+        _ => Precedence::Lowest,
+    }
+}
+
+fn is_assign_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Assign | Token::PlusEq | Token::MinusEq | Token::AsteriskEq | Token::SlashEq
+    )
+}
+
+fn is_infix_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Plus
+            | Token::Minus
+            | Token::Slash
+            | Token::Asterisk
+            | Token::Eq
+            | Token::NotEq
+            | Token::Lt
+            | Token::Gt
+            | Token::Le
+            | Token::Ge
+            | Token::BitAnd
+            | Token::BitOr
+            | Token::Caret
+            | Token::Shl
+            | Token::Shr
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -351,6 +841,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_let_null_statement() {
+        let mut parser = Parser::new(Lexer::new("let x = null;"));
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(
+            &Statement::Let("x".to_string(), Expression::Null),
+            program.get(0)
+        );
+        assert_eq!("let x = null;", program.to_string());
+    }
+
     #[test]
     fn test_return_statement() {
         let input = "
@@ -428,7 +931,7 @@ let foobar = true; let barfoo = false;";
 
     #[test]
     fn test_parse_prefix_expressions() {
-        let tests = vec![("!5", "!", 5), ("-15", "-", 15)];
+        let tests = vec![("!5", "!", 5), ("-15", "-", 15), ("~5", "~", 5)];
 
         for (input, operator, right) in tests {
             let lexer = Lexer::new(input);
@@ -452,7 +955,7 @@ let foobar = true; let barfoo = false;";
                             _ => panic!("right expression cannot match"),
                         }
                     }
-                    _ => panic!("expression cannot match: {}", expr.to_string()),
+                    _ => panic!("expression cannot match: {}", expr),
                 },
                 _ => panic!("statement cannot match"),
             }
@@ -468,6 +971,8 @@ let foobar = true; let barfoo = false;";
             ("5 / 5;", 5, "/", 5),
             ("5 > 5;", 5, ">", 5),
             ("5 < 5;", 5, "<", 5),
+            ("5 <= 5;", 5, "<=", 5),
+            ("5 >= 5;", 5, ">=", 5),
             ("5 == 5;", 5, "==", 5),
             ("5 != 5;", 5, "!=", 5),
         ];
@@ -500,7 +1005,7 @@ let foobar = true; let barfoo = false;";
                             _ => panic!("right expression cannot match"),
                         }
                     }
-                    _ => panic!("expression cannot match: {}", expr.to_string()),
+                    _ => panic!("expression cannot match: {}", expr),
                 },
                 _ => panic!("statement cannot match"),
             }
@@ -512,6 +1017,9 @@ let foobar = true; let barfoo = false;";
         let tests = vec![
             ("true", "true"),
             ("false", "false"),
+            ("10.0", "10.0"),
+            ("\"a\" + \"b\"", "(\"a\" + \"b\")"),
+            ("-1.5 * 2", "((-1.5) * 2)"),
             ("3 > 5 == false", "((3 > 5) == false)"),
             ("3 < 5 == true", "((3 < 5) == true)"),
             ("-a * b", "((-a) * b)"),
@@ -522,7 +1030,7 @@ let foobar = true; let barfoo = false;";
             ("a * b / c", "((a * b) / c)"),
             ("a + b / c", "(a + (b / c))"),
             ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
-            ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
+            ("3 + 4; -5 * 5", "(3 + 4);((-5) * 5)"),
             ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
             ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
             (
@@ -547,6 +1055,40 @@ let foobar = true; let barfoo = false;";
                 "add(a + b + c * d / f + g)",
                 "add((((a + b) + ((c * d) / f)) + g))",
             ),
+            ("(+)", "(+)"),
+            ("(-)(5, 3)", "(-)(5, 3)"),
+            ("(-5)", "(-5)"),
+            ("reduce(a, 0, (*))", "reduce(a, 0, (*))"),
+            ("1 << 2 + 3", "(1 << (2 + 3))"),
+            ("a & b | c ^ d", "((a & b) | (c ^ d))"),
+            ("a | b & c >> 1", "(a | (b & (c >> 1)))"),
+            ("a & b == c", "((a & b) == c)"),
+            ("x < y | z", "(x < (y | z))"),
+            ("~a & -b", "((~a) & (-b))"),
+            ("(<<)(1, 2)", "(<<)(1, 2)"),
+            ("a.len()", "a.len()"),
+            ("-a.len()", "(-a.len())"),
+            ("a + b.slice(1, c * 2)", "(a + b.slice(1, (c * 2)))"),
+            ("s.trim().upper()", "s.trim().upper()"),
+            ("(a + b).len()", "(a + b).len()"),
+            ("f(x).len()", "f(x).len()"),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("-a[0]", "(-(a[0]))"),
+            ("a[0][1]", "((a[0])[1])"),
+            ("f(x)[0].len()", "(f(x)[0]).len()"),
+            ("x > 0 ? 1 : -1", "((x > 0) ? 1 : (-1))"),
+            ("a ? b : c ? d : e", "(a ? b : (c ? d : e))"),
+            ("a ? b ? c : d : e", "(a ? (b ? c : d) : e)"),
+            ("a + b ? c * d : e == f", "((a + b) ? (c * d) : (e == f))"),
+            ("(a ? b : c) + 1", "((a ? b : c) + 1)"),
+            ("f(a ? b : c, d)", "f((a ? b : c), d)"),
         ];
 
         for (input, expected) in tests {
@@ -563,7 +1105,7 @@ let foobar = true; let barfoo = false;";
 
     #[test]
     fn test_if_expression() {
-        let input = "if (x < y) { x } ";
+        let input = "if (x < y) { x }";
 
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
@@ -577,7 +1119,7 @@ let foobar = true; let barfoo = false;";
 
         match program.get(0) {
             Statement::Expression(expr) => match expr {
-                Expression::If(_, conseq, _) => match conseq.get(0).unwrap() {
+                Expression::If(_, conseq, _) => match conseq.first().unwrap() {
                     Statement::Expression(expr) => {
                         assert_eq!("x", expr.to_string())
                     }
@@ -606,7 +1148,7 @@ let foobar = true; let barfoo = false;";
         match program.get(0) {
             Statement::Expression(expr) => match expr {
                 Expression::If(_, conseq, alter) => {
-                    match conseq.get(0).unwrap() {
+                    match conseq.first().unwrap() {
                         Statement::Expression(expr) => {
                             assert_eq!("x", expr.to_string());
                         }
@@ -614,7 +1156,7 @@ let foobar = true; let barfoo = false;";
                     }
 
                     if let Some(alter) = alter {
-                        match alter.get(0).unwrap() {
+                        match alter.first().unwrap() {
                             Statement::Expression(expr) => {
                                 assert_eq!("y", expr.to_string());
                             }
@@ -630,6 +1172,328 @@ let foobar = true; let barfoo = false;";
         }
     }
 
+    #[test]
+    fn test_match_expression() {
+        let input = "match (x + 1) { 1 => { \"one\" }, -2 => { \"minus two\" }, true => { 0 }, \"s\" => {}, _ => { x } }";
+
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(
+            "match (x + 1) { 1 => { \"one\" }, -2 => { \"minus two\" }, true => { 0 }, \"s\" => {  }, _ => { x } }",
+            program.to_string()
+        );
+
+        match program.get(0) {
+            Statement::Expression(Expression::Match(_, arms)) => {
+                let patterns: Vec<&Pattern> = arms.iter().map(|(pattern, _)| pattern).collect();
+                assert_eq!(
+                    vec![
+                        &Pattern::Int(1),
+                        &Pattern::Int(-2),
+                        &Pattern::Boolean(true),
+                        &Pattern::String("s".to_string()),
+                        &Pattern::Wildcard,
+                    ],
+                    patterns
+                );
+            }
+            _ => panic!("cannot match match expression"),
+        }
+
+        let tests = vec![
+            (
+                "match (x) { y => { 1 } }",
+                "expected a pattern, got y instead",
+            ),
+            (
+                "match (x) { 1 { 1 } }",
+                "expected next token to be Arrow, got Lbrace instead",
+            ),
+            (
+                "match (x) { 1 => { 1 } 2 => { 2 } }",
+                "expected next token to be Comma, got Int(2) instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                Some(expected.to_string()),
+                error_messages(&parser).first().cloned(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_while_statement() {
+        let input = "while (x < 10) { let x = x + 1; x }";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(1, program.len());
+        assert_eq!("while (x < 10) { let x = (x + 1);x }", program.to_string());
+
+        match program.get(0) {
+            Statement::While(cond, body) => {
+                assert_eq!("(x < 10)", cond.to_string());
+                assert_eq!(2, body.len());
+            }
+            _ => panic!("cannot match while statement"),
+        }
+    }
+
+    #[test]
+    fn test_for_in_statement() {
+        let input = "for (x in [1, 2]) { let y = x * 2; }";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(1, program.len());
+        assert_eq!(
+            "for (x in [1, 2]) { let y = (x * 2); }",
+            program.to_string()
+        );
+
+        for input in [
+            "for x in [1] { x }",
+            "for (1 in [1]) { 1 }",
+            "for (x [1]) { x }",
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert!(!error_messages(&parser).is_empty(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_block_statement() {
+        let tests = vec![
+            ("{ let x = 1; x }", "{ let x = 1;x }"),
+            ("{ x ? 1 : 2 }", "{ (x ? 1 : 2) }"),
+            ("{ {} } {}", "{ {} }{}"),
+            ("{ f({\"a\": 1}) }", "{ f({\"a\": 1}) }"),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert!(matches!(program.get(0), Statement::Block(_)), "{}", input);
+            assert_eq!(expected, program.to_string());
+        }
+
+        for input in ["{}", "{\"a\": 1}", "{x ? 1 : 2: 3}", "{[1][0]: 2}"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert!(
+                matches!(program.get(0), Statement::Expression(Expression::Hash(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_c_for_statement() {
+        let tests = vec![
+            (
+                "for (let i = 0; i < 5; i += 1) { puts(i); }",
+                "for (let i = 0; (i < 5); (i = (i + 1))) { puts(i) }",
+            ),
+            (
+                "for (i = 0; i < n; i = i + 1) {}",
+                "for ((i = 0); (i < n); (i = (i + 1))) {  }",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert_eq!(expected, program.to_string());
+            assert_eq!(
+                program,
+                Parser::new(Lexer::new(&program.to_string())).parse_program()
+            );
+        }
+
+        let tests = vec![
+            (
+                "for (let i = 0 i < 5; i += 1) {}",
+                "expected next token to be Semicolon, got Ident(\"i\") instead",
+            ),
+            (
+                "for (i = 0; i < 5) {}",
+                "expected next token to be Semicolon, got Rparen instead",
+            ),
+            (
+                "for (i = 0; i < 5; i += 1 {}",
+                "expected next token to be Rparen, got Lbrace instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                Some(expected.to_string()),
+                error_messages(&parser).first().cloned(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_break_continue_statements() {
+        let input = "while (true) { break; continue }";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        match program.get(0) {
+            Statement::While(_, body) => {
+                assert_eq!(vec![Statement::Break, Statement::Continue], *body);
+            }
+            _ => panic!("cannot match while statement"),
+        }
+    }
+
+    #[test]
+    fn test_assign_statement() {
+        let input = "x = x + 1; y = fn() { z = 2; };";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(2, program.len());
+
+        match program.get(0) {
+            Statement::Expression(Expression::Assign(name, value)) => {
+                assert_eq!("x", name);
+                assert_eq!("(x + 1)", value.to_string());
+            }
+            _ => panic!("cannot match assignment"),
+        }
+
+        assert_eq!("(y = fn() { (z = 2) })", program.get(1).to_string());
+    }
+
+    #[test]
+    fn test_assignment_is_right_associative() {
+        let mut parser = Parser::new(Lexer::new("a = b = 5"));
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(
+            &Statement::Expression(Expression::Assign(
+                "a".to_string(),
+                Box::new(Expression::Assign(
+                    "b".to_string(),
+                    Box::new(Expression::Int(5))
+                ))
+            )),
+            program.get(0)
+        );
+
+        let tests = vec![
+            ("a = b = 5", "(a = (b = 5))"),
+            ("a[0] = b += 1;", "(a[0] = (b = (b + 1)))"),
+            ("let x = y = 2;", "let x = (y = 2);"),
+            ("a = b ? 1 : 2", "(a = (b ? 1 : 2))"),
+            ("f(a = 1)", "f((a = 1))"),
+            ("(a = 1) + 2", "((a = 1) + 2)"),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert_eq!(expected, program.to_string(), "{}", input);
+        }
+
+        let mut parser = Parser::new(Lexer::new("1 + a = 5"));
+        parser.parse_program();
+        assert_eq!(vec!["cannot assign to (1 + a)"], error_messages(&parser));
+    }
+
+    #[test]
+    fn test_compound_assign_statement() {
+        let tests = vec![
+            ("x += 1", "(x = (x + 1))"),
+            ("x -= y * 2;", "(x = (x - (y * 2)))"),
+            ("x *= 3", "(x = (x * 3))"),
+            ("x /= 1 + 1", "(x = (x / (1 + 1)))"),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert_eq!(expected, program.to_string());
+        }
+    }
+
+    #[test]
+    fn test_index_assign_statement() {
+        let tests = vec![
+            ("a[0] = 1", "(a[0] = 1)"),
+            ("a[i + 1] = b[i];", "(a[(i + 1)] = (b[i]))"),
+            ("h[\"k\"] += 2", "(h[\"k\"] += 2)"),
+            ("a[i] *= b[i] + 1", "(a[i] *= ((b[i]) + 1))"),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert_eq!(expected, program.to_string());
+        }
+
+        let tests = vec![
+            ("1 = 2", "cannot assign to 1"),
+            ("f(x)[0] = 2", "cannot assign to (f(x)[0])"),
+            ("a[0][1] = 2", "cannot assign to ((a[0])[1])"),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+
+            assert_eq!(
+                vec![expected.to_string()],
+                error_messages(&parser),
+                "{}",
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_function_expression() {
         let input = "fn(x, y) { x + y; }";
@@ -644,6 +1508,45 @@ let foobar = true; let barfoo = false;";
         assert_eq!("fn(x, y) { (x + y) }", program.to_string());
     }
 
+    #[test]
+    fn test_do_expression() {
+        let mut parser = Parser::new(Lexer::new("let x = do { let a = 1; a + 1 }; do {}"));
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(
+            "let x = do { let a = 1;(a + 1) };do {  }",
+            program.to_string()
+        );
+
+        let mut parser = Parser::new(Lexer::new("do 1"));
+        parser.parse_program();
+        assert_eq!(
+            vec!["expected next token to be Lbrace, got Int(1) instead".to_string()],
+            error_messages(&parser)
+        );
+    }
+
+    #[test]
+    fn test_named_function_declaration() {
+        let named = Parser::new(Lexer::new("fn add(x, y) { x + y; } add(1, 2);")).parse_program();
+        let bound =
+            Parser::new(Lexer::new("let add = fn(x, y) { x + y; }; add(1, 2);")).parse_program();
+
+        assert_eq!(bound, named);
+        assert_eq!(
+            "let add = fn(x, y) { (x + y) };add(1, 2)",
+            named.to_string()
+        );
+
+        let mut parser = Parser::new(Lexer::new("fn add { }"));
+        parser.parse_program();
+        assert_eq!(
+            vec!["expected next token to be Lparen, got Lbrace instead".to_string()],
+            error_messages(&parser)
+        );
+    }
+
     #[test]
     fn test_function_parameter_parsing() {
         let tests = vec![
@@ -663,7 +1566,7 @@ let foobar = true; let barfoo = false;";
 
             match program.get(0) {
                 Statement::Expression(expr) => match expr {
-                    Expression::Function(parameters, _) => {
+                    Expression::Function(parameters, _, _) => {
                         assert_eq!(expected.len(), parameters.len());
 
                         for (i, &expect) in expected.iter().enumerate() {
@@ -677,6 +1580,52 @@ let foobar = true; let barfoo = false;";
         }
     }
 
+    #[test]
+    fn test_rest_parameter_parsing() {
+        let tests = vec![
+            ("fn(...rest) {}", vec![], Some("rest")),
+            ("fn(a, b, ...rest) {}", vec!["a", "b"], Some("rest")),
+            ("fn(a, b) {}", vec!["a", "b"], None),
+        ];
+
+        for (input, expected, expected_rest) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            match program.get(0) {
+                Statement::Expression(Expression::Function(parameters, rest, _)) => {
+                    assert_eq!(&expected, parameters);
+                    assert_eq!(expected_rest.map(str::to_string), *rest);
+                }
+                _ => panic!("unexpected match statement"),
+            }
+        }
+
+        let tests = vec![
+            (
+                "fn(...rest, a) {}",
+                "expected next token to be Rparen, got Comma instead",
+            ),
+            (
+                "fn(...) {}",
+                "expected next token to be Ident(\"\"), got Rparen instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+
+            assert_eq!(
+                Some(&expected.to_string()),
+                error_messages(&parser).first(),
+                "{}",
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_call_expression_parsing() {
         let input = "add(1, 2 * 3, 4 + 5);";
@@ -690,8 +1639,216 @@ let foobar = true; let barfoo = false;";
         assert_eq!(1, program.len());
     }
 
+    #[test]
+    fn test_array_literal_parsing() {
+        let tests = vec![
+            ("[]", "[]"),
+            ("[1, 2 * 2, 3 + 3]", "[1, (2 * 2), (3 + 3)]"),
+            ("[[1], [true, fn(x) { x }]]", "[[1], [true, fn(x) { x }]]"),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert_eq!(1, program.len());
+            assert!(matches!(
+                program.get(0),
+                Statement::Expression(Expression::Array(_))
+            ));
+            assert_eq!(expected, program.to_string());
+        }
+    }
+
+    #[test]
+    fn test_hash_literal_parsing() {
+        let tests = vec![
+            ("{}", "{}"),
+            ("{1: 2, true: 3}", "{1: 2, true: 3}"),
+            ("{1: 0 + 1, 2: 10 - 8}", "{1: (0 + 1), 2: (10 - 8)}"),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+
+            let program = parser.parse_program();
+
+            check_parser_errors(&parser);
+            assert_eq!(1, program.len());
+            assert!(matches!(
+                program.get(0),
+                Statement::Expression(Expression::Hash(_))
+            ));
+            assert_eq!(expected, program.to_string());
+        }
+    }
+
+    #[test]
+    fn test_illegal_token_errors() {
+        let tests = vec![
+            (
+                "let x = 99999999999999999999;",
+                "integer literal too large: 99999999999999999999",
+            ),
+            (
+                "return 1 + 99999999999999999999;",
+                "integer literal too large: 99999999999999999999",
+            ),
+            ("@", "illegal character: @"),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+
+            parser.parse_program();
+
+            assert_eq!(Some(&expected.to_string()), error_messages(&parser).first());
+        }
+    }
+
+    #[test]
+    fn test_malformed_expressions() {
+        let tests = vec![
+            ("(", "undefined expression for EOF found"),
+            ("(1", "expected next token to be Rparen, got Eof instead"),
+            ("5 +", "undefined expression for EOF found"),
+            ("if", "expected next token to be Lparen, got Eof instead"),
+            ("[1,", "undefined expression for EOF found"),
+            (
+                "[1, 2",
+                "expected next token to be Rbracket, got Eof instead",
+            ),
+            ("f(1, ;", "undefined expression for ; found"),
+            ("x.len(", "undefined expression for EOF found"),
+            ("-", "undefined expression for EOF found"),
+            ("!", "undefined expression for EOF found"),
+            ("~;", "undefined expression for ; found"),
+            ("if (", "undefined expression for EOF found"),
+            ("if () { 1 }", "undefined expression for ) found"),
+            ("let x = -;", "undefined expression for ; found"),
+            ("5 5", "unexpected token Int(5)"),
+            ("f(1) x = 2", "unexpected token Ident(\"x\")"),
+            ("fn(", "expected next token to be Rparen, got Eof instead"),
+            ("fn(a,", "expected next token to be Rparen, got Eof instead"),
+            (
+                "fn fib(",
+                "expected next token to be Rparen, got Eof instead",
+            ),
+            (
+                "while (true) {",
+                "expected next token to be Rbrace, got Eof instead",
+            ),
+            (
+                "if (x) {",
+                "expected next token to be Rbrace, got Eof instead",
+            ),
+            ("{", "expected next token to be Rbrace, got Eof instead"),
+            (
+                "fn() { 1",
+                "expected next token to be Rbrace, got Eof instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+
+            assert_eq!(
+                Some(&expected.to_string()),
+                error_messages(&parser).first(),
+                "{}",
+                input
+            );
+        }
+
+        let mut parser = Parser::new(Lexer::new("1 % 2"));
+        parser.parse_program();
+        assert_eq!(
+            1,
+            error_messages(&parser).len(),
+            "{:?}",
+            error_messages(&parser)
+        );
+
+        for input in ["5\n5", "5; 5", "if (x) { 5 } 5", "fn() { 5 }"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+
+            assert_eq!(Vec::<String>::new(), error_messages(&parser), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_large_program() {
+        let input: String = (0..50_000)
+            .map(|i| format!("let x = {} * (x + {});\n", i, i))
+            .collect();
+
+        let lexer = Lexer::new(&input);
+        let mut parser = Parser::new(lexer);
+
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        assert_eq!(50_000, program.len());
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let input = "let x 1;\n\n  let y = )\n\"é\" + @";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program();
+
+        let positions: Vec<(usize, usize)> =
+            parser.errors().iter().map(|e| (e.line, e.col)).collect();
+        assert_eq!(vec![(1, 7), (3, 11), (4, 7)], positions);
+    }
+
+    #[test]
+    fn test_error_carries_position() {
+        let mut parser = Parser::new(Lexer::new("let = 5;"));
+        parser.parse_program();
+
+        assert_eq!(
+            Some(&ParseError {
+                message: "expected next token to be Ident(\"\"), got Assign instead".to_string(),
+                line: 1,
+                col: 5,
+            }),
+            parser.errors().first()
+        );
+        assert_eq!(
+            "expected next token to be Ident(\"\"), got Assign instead",
+            parser.errors()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_statement_lines() {
+        let input = "let x = 1;\n\nlet f = fn() {\n  x\n};\n// comment\nf(); x\n";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+
+        check_parser_errors(&parser);
+        let lines: Vec<usize> = (0..program.len()).map(|i| program.line(i)).collect();
+        assert_eq!(vec![1, 3, 7, 7], lines);
+    }
+
+    fn error_messages(parser: &Parser) -> Vec<String> {
+        parser.errors().iter().map(|e| e.to_string()).collect()
+    }
+
     fn check_parser_errors(parser: &Parser) {
-        if parser.errors.len() > 0 {
+        if !parser.errors.is_empty() {
             for e in parser.errors.iter() {
                 println!("parser error: {}", e);
             }
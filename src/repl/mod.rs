@@ -1,13 +1,30 @@
-use crate::eval::eval_program;
+use crate::ast::{Expression, Statement};
+use crate::eval::{builtin_names, eval_program};
+use std::fs;
 use std::io::prelude::*;
 use std::io::BufRead;
 use std::io::BufReader;
 
-use crate::lexer::Lexer;
-use crate::object::Environment;
-use crate::parser::Parser;
+use crate::lexer::{tokenize, Lexer};
+use crate::object::{Env, Environment, Object};
+use crate::parser::{ParseError, Parser};
+use crate::token::Token;
 
 const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+const OPERATORS: &str = "+ - * / < > <= >= == != & | ^ << >> ! ~ = += -= *= /= ?: . []";
+const KEYWORDS: &str = "fn let true false null if else do match return while for in break continue";
+const COMMANDS: &str = ":help :tokens <source> :ast <source> :env :load <path> :exit (or quit)";
+
+#[derive(Debug, PartialEq)]
+pub enum ReplOutcome {
+    Value(Object),
+    NoValue,
+    ParseErrors(Vec<ParseError>),
+    Command(String),
+    Exit,
+}
 
 pub fn start<R, W>(input: R, output: W)
 where
@@ -16,29 +33,352 @@ where
 {
     let mut reader = BufReader::new(input);
     let mut writer = output;
-    let mut env = Environment::new();
+    let env = Environment::new().into();
+
+    let mut buffer = String::new();
 
     loop {
-        write!(writer, "{}", PROMPT).unwrap();
+        let prompt = if buffer.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        write!(writer, "{}", prompt).unwrap();
         writer.flush().unwrap();
 
-        let mut line = String::new();
-        if reader.read_line(&mut line).is_err() {
-            return;
+        match reader.read_line(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
         }
 
-        let lexer = Lexer::new(&line);
-        let mut parser = Parser::new(lexer);
-        let program = parser.parse_program();
-        if parser.errors().len() > 0 {
-            for err in parser.errors() {
-                writeln!(writer, "\t{}", err).unwrap();
-            }
+        if !buffer.trim_start().starts_with(':') && !input_is_complete(&buffer) {
             continue;
         }
 
-        if let Some(evaluated) = eval_program(program, &mut env) {
-            writeln!(writer, "{}", evaluated.to_string()).unwrap()
+        let input = std::mem::take(&mut buffer);
+        match step_repl(&input, &env) {
+            ReplOutcome::Value(evaluated) => writeln!(writer, "{}", evaluated.inspect()).unwrap(),
+            ReplOutcome::NoValue => {}
+            ReplOutcome::ParseErrors(errors) => {
+                for err in errors {
+                    writeln!(writer, "\t{}", err).unwrap();
+                }
+            }
+            ReplOutcome::Command(output) => writeln!(writer, "{}", output).unwrap(),
+            ReplOutcome::Exit => return,
+        }
+    }
+}
+
+// Runs one unit of REPL input against `env` without doing any IO, so other
+// frontends can drive the REPL themselves.
+pub fn step_repl(input: &str, env: &Env) -> ReplOutcome {
+    // A bare `quit` is accepted as well, since that's what newcomers tend to try.
+    if input.trim() == "quit" {
+        return ReplOutcome::Exit;
+    }
+
+    if let Some(command) = input.trim().strip_prefix(':') {
+        return run_command(command, env);
+    }
+
+    eval_source(input, env)
+}
+
+fn eval_source(source: &str, env: &Env) -> ReplOutcome {
+    let lexer = Lexer::new(source);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program();
+    if !parser.errors().is_empty() {
+        return ReplOutcome::ParseErrors(parser.errors());
+    }
+
+    let explicit_null = program.all().last().is_some_and(writes_null);
+    match eval_program(program, env) {
+        Some(Object::Null) if !explicit_null => ReplOutcome::NoValue,
+        Some(evaluated) => ReplOutcome::Value(evaluated),
+        None => ReplOutcome::NoValue,
+    }
+}
+
+// Like a shell, a null result prints nothing, unless the input spelled it out
+// as a `null` literal, on its own or as the value assigned, as in `x = null`.
+fn writes_null(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Expression(Expression::Null) => true,
+        Statement::Expression(Expression::Assign(_, value)) => **value == Expression::Null,
+        _ => false,
+    }
+}
+
+fn run_command(command: &str, env: &Env) -> ReplOutcome {
+    let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+
+    match name {
+        "exit" => ReplOutcome::Exit,
+        "help" => ReplOutcome::Command(format!(
+            "builtins: {}\noperators: {}\nkeywords: {}\ncommands: {}",
+            builtin_names().join(", "),
+            OPERATORS,
+            KEYWORDS,
+            COMMANDS
+        )),
+        "tokens" => {
+            let tokens: Vec<String> = tokenize(rest).iter().map(|t| t.to_string()).collect();
+            ReplOutcome::Command(tokens.join("\n"))
+        }
+        "env" => {
+            let entries = env.borrow().entries();
+            if entries.is_empty() {
+                return ReplOutcome::Command("no bindings".to_string());
+            }
+
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value.inspect()))
+                .collect();
+            ReplOutcome::Command(entries.join("\n"))
+        }
+        "ast" => {
+            let mut parser = Parser::new(Lexer::new(rest));
+            let program = parser.parse_program();
+            if !parser.errors().is_empty() {
+                return ReplOutcome::ParseErrors(parser.errors());
+            }
+
+            ReplOutcome::Command(program.to_string())
+        }
+        // Runs a script against the session's environment, so its bindings stay
+        // available to the lines that follow.
+        "load" => match fs::read_to_string(rest.trim()) {
+            Ok(source) => eval_source(&source, env),
+            Err(err) => ReplOutcome::Command(format!("cannot read {}: {}", rest.trim(), err)),
+        },
+        _ => ReplOutcome::Command(format!("unknown command: :{}", name)),
+    }
+}
+
+// Decides whether `input` closes every bracket it opens. It counts tokens rather
+// than characters so brackets inside strings and comments are ignored.
+pub fn input_is_complete(input: &str) -> bool {
+    let mut lexer = Lexer::new(input);
+    let mut depth = 0;
+
+    loop {
+        match lexer.next_token() {
+            Token::Lparen | Token::Lbrace | Token::Lbracket => depth += 1,
+            Token::Rparen | Token::Rbrace | Token::Rbracket => depth -= 1,
+            Token::Eof => return depth <= 0,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{HashKey, HashPairs};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_step_repl() {
+        let env = Environment::new().into();
+
+        assert_eq!(
+            ReplOutcome::Value(Object::Integer(3)),
+            step_repl("1 + 2", &env)
+        );
+        assert_eq!(ReplOutcome::NoValue, step_repl("let x = 5;", &env));
+        assert_eq!(
+            ReplOutcome::Value(Object::Integer(10)),
+            step_repl("x * 2", &env)
+        );
+        assert_eq!(
+            ReplOutcome::Value(Object::Hash(
+                HashPairs::from([(HashKey::String("a".to_string()), Object::Integer(1))]).into()
+            )),
+            step_repl("{\"a\": 1}", &env)
+        );
+        assert!(matches!(
+            step_repl("let = 5;", &env),
+            ReplOutcome::ParseErrors(errors) if !errors.is_empty()
+        ));
+        assert_eq!(ReplOutcome::Exit, step_repl(":exit\n", &env));
+        assert_eq!(ReplOutcome::Exit, step_repl("  quit \n", &env));
+        assert_eq!(
+            ReplOutcome::Command("unknown command: :nope".to_string()),
+            step_repl(":nope", &env)
+        );
+        assert_eq!(
+            ReplOutcome::Command("x\n+\n\"}\"".to_string()),
+            step_repl(":tokens x + \"}\"\n", &env)
+        );
+    }
+
+    #[test]
+    fn test_start_stops_on_exit() {
+        let mut output = vec![];
+        start(
+            "1 + 2\n\"a\" + \"b\"\n:exit\n3 + 4\n".as_bytes(),
+            &mut output,
+        );
+
+        assert_eq!(">> 3\n>> \"ab\"\n>> ", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_start_stops_on_quit_or_eof() {
+        let mut output = vec![];
+        start("1\nquit\n2\n".as_bytes(), &mut output);
+        assert_eq!(">> 1\n>> ", String::from_utf8(output).unwrap());
+
+        let mut output = vec![];
+        start(":exit\n".as_bytes(), &mut output);
+        assert_eq!(">> ", String::from_utf8(output).unwrap());
+
+        let mut output = vec![];
+        start("".as_bytes(), &mut output);
+        assert_eq!(">> ", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_start_echoes_last_statement_of_a_line() {
+        let mut output = vec![];
+        start(
+            "let x = 5; x * 2\nx + 1; let y = x;\ny; x - 1;\n".as_bytes(),
+            &mut output,
+        );
+
+        assert_eq!(">> 10\n>> >> 4\n>> ", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_start_skips_null_values() {
+        let mut output = vec![];
+        start(
+            concat!(
+                "if (false) { 10 }\nputs(1)\nif (true) { 10 }\nnull\nlet x = 1; x = null\n",
+                "let f = fn() { null }; f()\nlet p = puts; p(1)\ndo {}\n",
+            )
+            .as_bytes(),
+            &mut output,
+        );
+
+        assert_eq!(
+            ">> >> >> 10\n>> null\n>> null\n>> >> >> >> ",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_start_reads_multi_line_input() {
+        let mut output = vec![];
+        start(
+            "if (true) {\n 10 \n}\nlet f = fn(x) {\n  x * 2\n};\nf(4)\n".as_bytes(),
+            &mut output,
+        );
+
+        assert_eq!(
+            ">> ... ... 10\n>> ... ... >> 8\n>> ",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ast_command() {
+        let mut output = vec![];
+        start(":ast 1 + 2 * 3\n:ast let = 1;\n".as_bytes(), &mut output);
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("(1 + (2 * 3))"), "{}", output);
+        assert!(
+            output.contains("\texpected next token to be Ident(\"\"), got Assign instead"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_help_command() {
+        let mut output = vec![];
+        start(":help\n".as_bytes(), &mut output);
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("builtins: abs, assert, assert_eq,"),
+            "{}",
+            output
+        );
+        assert!(output.contains(" len, "), "{}", output);
+        assert!(output.contains("keywords: fn let "), "{}", output);
+        assert!(output.contains(":help"), "{}", output);
+    }
+
+    #[test]
+    fn test_env_command() {
+        let env = Environment::new().into();
+
+        assert_eq!(
+            ReplOutcome::Command("no bindings".to_string()),
+            step_repl(":env", &env)
+        );
+
+        step_repl("let name = \"maymun\"; let count = 2;", &env);
+        assert_eq!(
+            ReplOutcome::Command("count = 2\nname = \"maymun\"".to_string()),
+            step_repl(":env", &env)
+        );
+    }
+
+    #[test]
+    fn test_load_command() {
+        let dir = TempDir::new("load_command");
+        let path = dir.path("script.mmn");
+        fs::write(
+            &path,
+            "let double = fn(x) {\n  x * 2\n};\nlet loaded = true;\n",
+        )
+        .unwrap();
+
+        let mut output = vec![];
+        start(
+            format!(":load {}\ndouble(21)\n", path.display()).as_bytes(),
+            &mut output,
+        );
+        assert_eq!(">> >> 42\n>> ", String::from_utf8(output).unwrap());
+
+        let env = Environment::new().into();
+        let missing = dir.path("missing.mmn");
+        assert!(matches!(
+            step_repl(&format!(":load {}", missing.display()), &env),
+            ReplOutcome::Command(msg) if msg.starts_with("cannot read ")
+        ));
+
+        fs::write(&path, "let = 1;").unwrap();
+        assert!(matches!(
+            step_repl(&format!(":load {}", path.display()), &env),
+            ReplOutcome::ParseErrors(errors) if !errors.is_empty()
+        ));
+        assert_eq!(ReplOutcome::Value(Object::Integer(1)), step_repl("1", &env));
+    }
+
+    #[test]
+    fn test_input_is_complete() {
+        let tests = vec![
+            ("let x = 5;", true),
+            ("let s = \"{\";", true),
+            ("let s = \"(\" + \"[\";", true),
+            ("1 + 2 // {", true),
+            ("fn(x) {\n  x\n}", true),
+            ("}", true),
+            ("if (true) {", false),
+            ("let f = fn(x) {\n  let s = \"}\";", false),
+            ("[1, 2,", false),
+            ("add(1,", false),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(expected, input_is_complete(input), "{}", input);
         }
     }
 }
@@ -0,0 +1,26 @@
+use std::fs;
+use std::path::PathBuf;
+
+// A directory of a test's own under the system temp directory, named after the
+// test and the process so parallel runs can't collide. It is removed when
+// dropped, which also happens when an assertion fails.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("maymun-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub(crate) fn path(&self, file: &str) -> PathBuf {
+        self.0.join(file)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
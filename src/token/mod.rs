@@ -1,62 +1,159 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+use crate::lexer::escape;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Token {
-    Illegal,
+    Illegal(String), // reason the input could not be tokenized
     Eof,
 
     // Identifiers + literals
-    Ident(String), // add, foobar, x, y, ...
-    Int(i64),      // 1234
+    Ident(String),  // add, foobar, x, y, ...
+    Int(i64),       // 1234
+    Float(f64),     // 3.14
+    String(String), // "foobar"
 
     // Operators
     Assign,   // =
     Plus,     // +
     Minus,    // -
     Bang,     // !
+    Tilde,    // ~
     Asterisk, // *
     Slash,    // /
+    BitAnd,   // &
+    BitOr,    // |
+    Caret,    // ^
+    Shl,      // <<
+    Shr,      // >>
+
+    // Compound assignment
+    PlusEq,     // +=
+    MinusEq,    // -=
+    AsteriskEq, // *=
+    SlashEq,    // /=
 
     // Comparisons
     Lt,    // <
     Gt,    // >
+    Le,    // <=
+    Ge,    // >=
     Eq,    // ==
     NotEq, // !=
 
     // Delimiters
     Comma,     // ,
     Semicolon, // ;
+    Colon,     // :
+    Dot,       // .
+    Ellipsis,  // ...
+    Question,  // ?
+    Arrow,     // =>
 
     // Scopes
-    Lparen, // (
-    Rparen, // )
-    Lbrace, // {
-    Rbrace, // }
+    Lparen,   // (
+    Rparen,   // )
+    Lbrace,   // {
+    Rbrace,   // }
+    Lbracket, // [
+    Rbracket, // ]
 
     // Keywords (reserved)
     Function, // fn
     Let,      // let
     True,     // true
     False,    // false
+    Null,     // null
     If,       // if
     Else,     // else
+    Do,       // do
+    Match,    // match
     Return,   // return
+    While,    // while
+    For,      // for
+    In,       // in
+    Break,    // break
+    Continue, // continue
 }
 
+// Prints a token as it would appear in source.
 impl Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            Token::Illegal(reason) => write!(f, "{}", reason),
+            Token::Eof => write!(f, "EOF"),
+            Token::Ident(ident) => write!(f, "{}", ident),
+            Token::Int(i) => write!(f, "{}", i),
+            Token::Float(float) => write!(f, "{:?}", float),
+            Token::String(s) => write!(f, "\"{}\"", escape(s)),
             Token::Assign => write!(f, "="),
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
             Token::Bang => write!(f, "!"),
+            Token::Tilde => write!(f, "~"),
             Token::Asterisk => write!(f, "*"),
             Token::Slash => write!(f, "/"),
+            Token::BitAnd => write!(f, "&"),
+            Token::BitOr => write!(f, "|"),
+            Token::Caret => write!(f, "^"),
+            Token::Shl => write!(f, "<<"),
+            Token::Shr => write!(f, ">>"),
+            Token::PlusEq => write!(f, "+="),
+            Token::MinusEq => write!(f, "-="),
+            Token::AsteriskEq => write!(f, "*="),
+            Token::SlashEq => write!(f, "/="),
             Token::Gt => write!(f, ">"),
             Token::Lt => write!(f, "<"),
+            Token::Le => write!(f, "<="),
+            Token::Ge => write!(f, ">="),
             Token::Eq => write!(f, "=="),
             Token::NotEq => write!(f, "!="),
-            _ => Debug::fmt(self, f),
+            Token::Comma => write!(f, ","),
+            Token::Semicolon => write!(f, ";"),
+            Token::Colon => write!(f, ":"),
+            Token::Dot => write!(f, "."),
+            Token::Ellipsis => write!(f, "..."),
+            Token::Question => write!(f, "?"),
+            Token::Arrow => write!(f, "=>"),
+            Token::Lparen => write!(f, "("),
+            Token::Rparen => write!(f, ")"),
+            Token::Lbrace => write!(f, "{{"),
+            Token::Rbrace => write!(f, "}}"),
+            Token::Lbracket => write!(f, "["),
+            Token::Rbracket => write!(f, "]"),
+            Token::Function => write!(f, "fn"),
+            Token::Let => write!(f, "let"),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::Null => write!(f, "null"),
+            Token::If => write!(f, "if"),
+            Token::Else => write!(f, "else"),
+            Token::Do => write!(f, "do"),
+            Token::Match => write!(f, "match"),
+            Token::Return => write!(f, "return"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::In => write!(f, "in"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+        }
+    }
+}
+
+// The lexer only produces finite, non-negative floats, which compare equal to
+// themselves, so tokens can still be used as keys.
+impl Eq for Token {}
+
+impl Hash for Token {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Token::Illegal(s) | Token::Ident(s) | Token::String(s) => s.hash(state),
+            Token::Int(i) => i.hash(state),
+            // `0.0 == -0.0`, so the two must hash alike.
+            Token::Float(float) => (float + 0.0).to_bits().hash(state),
+            _ => {}
         }
     }
 }
@@ -67,9 +164,50 @@ pub fn lookup_ident(ident: &str) -> Token {
         "let" => Token::Let,
         "true" => Token::True,
         "false" => Token::False,
+        "null" => Token::Null,
         "if" => Token::If,
         "else" => Token::Else,
+        "do" => Token::Do,
+        "match" => Token::Match,
         "return" => Token::Return,
+        "while" => Token::While,
+        "for" => Token::For,
+        "in" => Token::In,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
         _ => Token::Ident(ident.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_display() {
+        assert_eq!("foo", Token::Ident("foo".into()).to_string());
+        assert_eq!("let", Token::Let.to_string());
+        assert_eq!("42", Token::Int(42).to_string());
+        assert_eq!("2.0", Token::Float(2.0).to_string());
+        assert_eq!("\"a\\\"b\\n\"", Token::String("a\"b\n".into()).to_string());
+        assert_eq!("{", Token::Lbrace.to_string());
+    }
+
+    #[test]
+    fn test_tokens_hash() {
+        let tokens: HashSet<Token> = tokenize("x 1.5 x 1.5 0.0").into_iter().collect();
+
+        assert_eq!(3, tokens.len());
+        assert!(tokens.contains(&Token::Float(1.5)));
+    }
+
+    #[test]
+    fn test_display_round_trips_source() {
+        let input = r#"let add = fn(x, y) { if (x != y) { return x <<= y; } else { [1.5, "s\t", x.len()] } };"#;
+
+        let printed: Vec<String> = tokenize(input).iter().map(|t| t.to_string()).collect();
+        assert_eq!(tokenize(input), tokenize(&printed.join(" ")));
+    }
+}
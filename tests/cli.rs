@@ -0,0 +1,97 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Each script gets a directory of its own, so parallel tests can't collide, and
+// the directory is gone again before any assertion runs.
+fn run_script(name: &str, source: &str) -> (i32, String, String) {
+    let dir = std::env::temp_dir().join(format!("maymun-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("script.mmn");
+    fs::write(&path, source).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_maymun-lang"))
+        .arg(&path)
+        .output();
+    fs::remove_dir_all(&dir).unwrap();
+    let out = out.unwrap();
+
+    (
+        out.status.code().unwrap(),
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_run_script() {
+    assert_eq!(
+        (0, "6\n".to_string(), String::new()),
+        run_script("run_script", "let x = 5; x + 1;")
+    );
+}
+
+#[test]
+fn test_run_script_without_value() {
+    assert_eq!(
+        (0, String::new(), String::new()),
+        run_script("run_script_without_value", "let x = 5;")
+    );
+}
+
+#[test]
+fn test_run_script_without_final_null() {
+    assert_eq!(
+        (0, "hi\n".to_string(), String::new()),
+        run_script("run_script_without_final_null", "puts(\"hi\");")
+    );
+    assert_eq!(
+        (0, String::new(), String::new()),
+        run_script("run_script_null", "null")
+    );
+}
+
+#[test]
+fn test_run_script_parse_errors() {
+    let (code, stdout, stderr) = run_script("run_script_parse_errors", "let x 5;");
+
+    assert_eq!(1, code);
+    assert_eq!("", stdout);
+    assert_eq!(
+        "\texpected next token to be Assign, got Int(5) instead\n",
+        stderr
+    );
+}
+
+#[test]
+fn test_run_piped_source() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_maymun-lang"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"let x = 1 + 2;\nputs(x);\nx * 2\n")
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+
+    assert_eq!(Some(0), out.status.code());
+    assert_eq!("3\n6\n", String::from_utf8(out.stdout).unwrap());
+    assert_eq!("", String::from_utf8(out.stderr).unwrap());
+}
+
+#[test]
+fn test_eval_flag() {
+    let out = Command::new(env!("CARGO_BIN_EXE_maymun-lang"))
+        .args(["-e", "puts(3 * 3)"])
+        .output()
+        .unwrap();
+
+    assert_eq!(Some(0), out.status.code());
+    assert_eq!("9\n", String::from_utf8(out.stdout).unwrap());
+    assert_eq!("", String::from_utf8(out.stderr).unwrap());
+}